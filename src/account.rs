@@ -0,0 +1,243 @@
+use std::{net::IpAddr, sync::LazyLock, time::Duration};
+
+use anyhow::Context;
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use deadpool_redis::Pool;
+use redis::AsyncCommands;
+use redis_macros::{FromRedisValue, ToRedisArgs};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{session::JoinedTodoList, user::User};
+
+/// Verified against when logging into unknown accounts, so that they take as long as
+/// logging into existing ones.
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    Argon2::default()
+        .hash_password(b"not a password", &salt)
+        .expect("Failed to hash dummy password")
+        .to_string()
+});
+
+/// Username and password, as sent to register or log in.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Usernames have 3 to 32 letters, digits, `-` or `_`; passwords 8 to 128 characters.
+    pub fn is_valid(&self) -> bool {
+        let username_len = self.username.chars().count();
+        let password_len = self.password.chars().count();
+        (3..=32).contains(&username_len)
+            && self
+                .username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && (8..=128).contains(&password_len)
+    }
+}
+
+/// A persistent identity, that any session can be attached to by logging in.
+/// Usernames are case insensitive.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRedisValue, ToRedisArgs)]
+pub struct Account {
+    username: String,
+    password_hash: String,
+    user: User,
+}
+
+/// What clients are told about an account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct AccountInfo {
+    pub username: String,
+    pub user: User,
+}
+
+impl Account {
+    /// Turns `user` into an account. Returns `None` if the username is taken.
+    pub async fn register(
+        credentials: Credentials,
+        user: User,
+        pool: Pool,
+    ) -> anyhow::Result<Option<Self>> {
+        let password_hash = tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut rand::thread_rng());
+            Argon2::default()
+                .hash_password(credentials.password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+        })
+        .await?
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {e}"))?;
+        let account = Self {
+            username: credentials.username,
+            password_hash,
+            user,
+        };
+        let mut redis = pool.get().await?;
+        let created: bool = redis
+            .set_nx(account_key(&account.username), &account)
+            .await?;
+
+        Ok(created.then_some(account))
+    }
+
+    /// Returns the account if the credentials match one.
+    pub async fn login(credentials: Credentials, pool: Pool) -> anyhow::Result<Option<Self>> {
+        let mut redis = pool.get().await?;
+        let account: Option<Self> = redis.get(account_key(&credentials.username)).await?;
+        let password_hash = account.as_ref().map_or_else(
+            || DUMMY_PASSWORD_HASH.clone(),
+            |account| account.password_hash.clone(),
+        );
+        let verified = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&password_hash).map(|hash| {
+                Argon2::default()
+                    .verify_password(credentials.password.as_bytes(), &hash)
+                    .is_ok()
+            })
+        })
+        .await?
+        .map_err(|e| anyhow::anyhow!("Invalid password hash: {e}"))?;
+
+        Ok(account.filter(|_| verified))
+    }
+
+    pub const fn user(&self) -> &User {
+        &self.user
+    }
+
+    pub fn info(&self) -> AccountInfo {
+        AccountInfo {
+            username: self.username.clone(),
+            user: self.user.clone(),
+        }
+    }
+
+    /// Lists joined by a registered user, from any device.
+    pub async fn joined_lists(user: Uuid, pool: Pool) -> anyhow::Result<Vec<JoinedTodoList>> {
+        let mut redis = pool.get().await?;
+        let lists: Option<String> = redis.get(lists_key(user)).await?;
+        lists.map_or(Ok(vec![]), |lists| {
+            serde_json::from_str(&lists).context("Failed to parse joined lists")
+        })
+    }
+
+    /// Applies `update` to the lists joined by a registered user. It is applied again if the
+    /// lists have been changed meanwhile, e.g. from another device.
+    pub async fn update_joined_lists<T>(
+        user: Uuid,
+        pool: Pool,
+        mut update: impl FnMut(&mut Vec<JoinedTodoList>) -> T,
+    ) -> anyhow::Result<T> {
+        let mut redis = pool.get().await?;
+        loop {
+            redis::cmd("WATCH")
+                .arg(lists_key(user))
+                .query_async::<_, ()>(&mut redis)
+                .await?;
+            let lists: Option<String> = redis.get(lists_key(user)).await?;
+            let mut lists = match lists.map_or(Ok(vec![]), |lists| serde_json::from_str(&lists)) {
+                Ok(lists) => lists,
+                Err(e) => {
+                    redis::cmd("UNWATCH")
+                        .query_async::<_, ()>(&mut redis)
+                        .await?;
+                    return Err(e).context("Failed to parse joined lists");
+                }
+            };
+            let result = update(&mut lists);
+            // Aborted if the lists have been changed since being watched
+            let stored: Option<()> = redis::pipe()
+                .atomic()
+                .set(lists_key(user), serde_json::to_string(&lists)?)
+                .ignore()
+                .query_async(&mut redis)
+                .await?;
+            if stored.is_some() {
+                return Ok(result);
+            }
+        }
+    }
+
+    pub async fn store_joined_lists(
+        user: Uuid,
+        lists: &[JoinedTodoList],
+        pool: Pool,
+    ) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis
+            .set::<_, _, ()>(lists_key(user), serde_json::to_string(lists)?)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Limits failed logins into each account from each address to `max_attempts` per `window`.
+/// Keying on the address too keeps others from locking the account's owner out.
+#[derive(Debug, Clone, Copy)]
+pub struct LoginLimiter {
+    max_attempts: u32,
+    window: Duration,
+}
+
+impl LoginLimiter {
+    pub const fn new(max_attempts: u32, window: Duration) -> Self {
+        Self {
+            max_attempts,
+            window,
+        }
+    }
+
+    /// Counts an attempt to log into `username` from `address`. Returns whether it is allowed.
+    pub async fn try_attempt(
+        &self,
+        address: IpAddr,
+        username: &str,
+        pool: Pool,
+    ) -> anyhow::Result<bool> {
+        let mut redis = pool.get().await?;
+        let key = attempts_key(address, username);
+        // The window starts with the first attempt, and the counter can't outlive it
+        let (attempts,): (u32,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.window.as_secs().max(1))
+            .ignore()
+            .incr(&key, 1)
+            .query_async(&mut redis)
+            .await?;
+
+        Ok(attempts <= self.max_attempts)
+    }
+
+    /// Forgets the attempts made before logging in successfully.
+    pub async fn reset(&self, address: IpAddr, username: &str, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis.del::<_, ()>(attempts_key(address, username)).await?;
+        Ok(())
+    }
+}
+
+fn account_key(username: &str) -> String {
+    format!("account:{}", username.to_lowercase())
+}
+
+fn attempts_key(address: IpAddr, username: &str) -> String {
+    format!("login_attempts:{address}:{}", username.to_lowercase())
+}
+
+fn lists_key(user: Uuid) -> String {
+    format!("user_lists:{user}")
+}
@@ -1,3 +1,7 @@
+pub mod account;
+pub mod message;
+pub mod notification;
+pub mod rate_limit;
 pub mod routes;
 pub mod session;
 pub mod settings;
@@ -6,3 +10,4 @@ pub mod state;
 pub mod telemetry;
 pub mod todo;
 pub mod user;
+pub mod wire;
@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+
+use json_patch::Patch;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    notification::Notification,
+    todo::{ChatEvent, CommandError, TodoList, TodoListPresence, TodoListStats},
+    user::User,
+};
+
+/// Version of the messages exchanged over WebSockets, announced in [`ServerMessage::Hello`].
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Every message pushed by the server to a connected client.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ServerMessage<'t> {
+    /// First message of every connection.
+    Hello {
+        version: u32,
+        user: User,
+    },
+    /// Full state of the list, replacing whatever the client had.
+    Snapshot(Cow<'t, TodoList>),
+    /// Changes to apply on top of the last snapshot or patch.
+    Patch {
+        /// Revision of the list once the patch has been applied.
+        revision: u64,
+        /// RFC 6902 operations.
+        #[schemars(with = "Vec<serde_json::Value>")]
+        ops: Patch,
+    },
+    /// The command with the given id has been applied.
+    Ack {
+        id: String,
+        revision: u64,
+        /// Tasks whose blockers have all been completed by the command.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        unblocked: Vec<Uuid>,
+    },
+    /// Tasks whose blockers have all been completed by the change that brought the list
+    /// to `revision`. Sent to every connection, whoever made the change.
+    Unblocked {
+        revision: u64,
+        tasks: Vec<Uuid>,
+    },
+    Error {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(flatten)]
+        error: CommandError,
+    },
+    Stats(TodoListStats),
+    /// Users connected to the list and what they are up to.
+    Presence(TodoListPresence),
+    /// Change to the list's chat.
+    Chat(ChatEvent),
+    /// Sent to every open connection of the notification's recipient, with the same id.
+    Notification(Notification),
+    /// Something about the connection itself, usually why it's about to be closed.
+    Notice {
+        kind: NoticeKind,
+        message: String,
+    },
+    Pong,
+}
+
+impl ServerMessage<'_> {
+    pub fn hello(user: User) -> Self {
+        Self::Hello {
+            version: PROTOCOL_VERSION,
+            user,
+        }
+    }
+
+    pub fn notice(kind: NoticeKind) -> Self {
+        let message = match kind {
+            NoticeKind::IdleTimeout => "Connection closed after being idle for too long",
+        };
+        Self::Notice {
+            kind,
+            message: message.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoticeKind {
+    IdleTimeout,
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use deadpool_redis::Pool;
+use redis::AsyncCommands;
+use redis_macros::{FromRedisValue, ToRedisArgs};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{todo::TextField, user::User};
+
+/// Notifications kept in each user's inbox, older ones being dropped.
+const INBOX_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// The recipient's handle has been added to one of a task's texts.
+    Mention { field: TextField },
+    /// The task has been assigned to the recipient.
+    Assignment,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, FromRedisValue, ToRedisArgs,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    id: Uuid,
+    recipient: Uuid,
+    #[serde(flatten)]
+    kind: NotificationKind,
+    list: Uuid,
+    task: Uuid,
+    by: User,
+    created_at: DateTime<Utc>,
+    /// Only meaningful in an [`Inbox`]: read state isn't stored with the notification.
+    #[serde(default)]
+    read: bool,
+}
+
+impl Notification {
+    pub fn new(kind: NotificationKind, recipient: Uuid, list: Uuid, task: Uuid, by: User) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            recipient,
+            kind,
+            list,
+            task,
+            by,
+            created_at: Utc::now(),
+            read: false,
+        }
+    }
+
+    pub const fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub const fn recipient(&self) -> Uuid {
+        self.recipient
+    }
+
+    pub const fn kind(&self) -> &NotificationKind {
+        &self.kind
+    }
+
+    pub const fn is_read(&self) -> bool {
+        self.read
+    }
+
+    async fn store(&self, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        let (len,): (usize,) = redis::pipe()
+            .atomic()
+            .lpush(inbox_key(self.recipient), self)
+            .sadd(unread_key(self.recipient), self.id.to_string())
+            .ignore()
+            .query_async(&mut redis)
+            .await?;
+        if len > INBOX_CAPACITY {
+            let dropped: Option<Notification> = redis.rpop(inbox_key(self.recipient), None).await?;
+            if let Some(dropped) = dropped {
+                redis
+                    .srem::<_, _, ()>(unread_key(self.recipient), dropped.id.to_string())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A user's notifications, most recent first.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Inbox {
+    pub unread: usize,
+    pub notifications: Vec<Notification>,
+}
+
+impl Inbox {
+    pub async fn from_redis(user: Uuid, pool: Pool) -> anyhow::Result<Self> {
+        let mut redis = pool.get().await?;
+        let (mut notifications, unread): (Vec<Notification>, HashSet<String>) = redis::pipe()
+            .lrange(inbox_key(user), 0, -1)
+            .smembers(unread_key(user))
+            .query_async(&mut redis)
+            .await?;
+        for notification in &mut notifications {
+            notification.read = !unread.contains(&notification.id.to_string());
+        }
+
+        Ok(Self {
+            unread: unread.len(),
+            notifications,
+        })
+    }
+
+    pub async fn unread_count(user: Uuid, pool: Pool) -> anyhow::Result<usize> {
+        let mut redis = pool.get().await?;
+        Ok(redis.scard(unread_key(user)).await?)
+    }
+
+    /// Returns whether the notification was unread.
+    pub async fn mark_read(user: Uuid, notification: Uuid, pool: Pool) -> anyhow::Result<bool> {
+        let mut redis = pool.get().await?;
+        let removed: usize = redis
+            .srem(unread_key(user), notification.to_string())
+            .await?;
+        Ok(removed > 0)
+    }
+
+    pub async fn mark_all_read(user: Uuid, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis.del::<_, ()>(unread_key(user)).await?;
+        Ok(())
+    }
+}
+
+/// Open connections of each user.
+type Subscribers = Arc<Mutex<HashMap<Uuid, Vec<(Uuid, mpsc::UnboundedSender<Notification>)>>>>;
+
+/// Stores notifications and pushes them to the recipients' open connections.
+#[derive(Clone)]
+pub struct Notifier {
+    pool: Pool,
+    subscribers: Subscribers,
+}
+
+impl Notifier {
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    /// Notifications sent to `user` from now on, through one of their connections.
+    pub fn subscribe(&self, user: Uuid) -> NotificationReceiver {
+        let id = Uuid::new_v4();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers
+            .lock()
+            .expect("Notification subscribers poisoned")
+            .entry(user)
+            .or_default()
+            .push((id, sender));
+
+        NotificationReceiver {
+            user,
+            id,
+            receiver,
+            subscribers: self.subscribers.clone(),
+        }
+    }
+
+    /// Users aren't notified of their own doings.
+    pub async fn send(&self, notification: Notification) -> anyhow::Result<()> {
+        if notification.recipient == *notification.by.id() {
+            return Ok(());
+        }
+        notification.store(self.pool.clone()).await?;
+        self.push(notification);
+        Ok(())
+    }
+
+    /// Pushes a notification to every open connection of the recipient, which may be on
+    /// different devices. Clients connected several times tell copies apart by their id, the
+    /// inbox only has one. Nobody being connected is fine, the notification is in the inbox.
+    fn push(&self, notification: Notification) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .expect("Notification subscribers poisoned");
+        let recipient = notification.recipient;
+        let Some(connections) = subscribers.get_mut(&recipient) else {
+            return;
+        };
+        connections.retain(|(_, sender)| sender.send(notification.clone()).is_ok());
+        if connections.is_empty() {
+            subscribers.remove(&recipient);
+        }
+    }
+
+    /// Notifies the users whose handle has been added to a text.
+    pub async fn mentions(
+        &self,
+        before: &str,
+        after: &str,
+        notification: impl Fn(Uuid) -> Notification,
+    ) -> anyhow::Result<()> {
+        let already_mentioned = mentions(before);
+        for handle in mentions(after).difference(&already_mentioned) {
+            if let Some(user) = User::from_handle(handle, self.pool.clone()).await? {
+                self.send(notification(*user.id())).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Notifications pushed to one of a user's connections.
+pub struct NotificationReceiver {
+    user: Uuid,
+    id: Uuid,
+    receiver: mpsc::UnboundedReceiver<Notification>,
+    subscribers: Subscribers,
+}
+
+impl NotificationReceiver {
+    pub async fn recv(&mut self) -> Option<Notification> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for NotificationReceiver {
+    fn drop(&mut self) {
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return;
+        };
+        if let Some(connections) = subscribers.get_mut(&self.user) {
+            connections.retain(|(id, _)| *id != self.id);
+            if connections.is_empty() {
+                subscribers.remove(&self.user);
+            }
+        }
+    }
+}
+
+/// Handles mentioned in a text, as `@handle`. An `@` within a word, as in an email
+/// address, isn't a mention.
+pub fn mentions(text: &str) -> HashSet<&str> {
+    let is_handle_char = |c: char| c.is_ascii_alphanumeric() || c == '-';
+    text.match_indices('@')
+        .filter(|(at, _)| !text[..*at].ends_with(is_handle_char))
+        .filter_map(|(at, _)| {
+            let mention = &text[at + 1..];
+            let end = mention
+                .find(|c: char| !is_handle_char(c))
+                .unwrap_or(mention.len());
+            let handle = mention[..end].trim_end_matches('-');
+            (!handle.is_empty()).then_some(handle)
+        })
+        .collect()
+}
+
+fn inbox_key(user: Uuid) -> String {
+    format!("notifications:{user}")
+}
+
+fn unread_key(user: Uuid) -> String {
+    format!("unread_notifications:{user}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use uuid::Uuid;
+
+    use super::{mentions, Notification, NotificationKind, Notifier};
+    use crate::user::User;
+
+    #[test]
+    fn mentions_are_found() {
+        assert_eq!(
+            mentions("Ask @merry-quiet-otter, cc @bold-fox. Not me@example.com"),
+            HashSet::from(["merry-quiet-otter", "bold-fox"])
+        );
+        assert!(mentions("No one here @ all").is_empty());
+    }
+
+    #[tokio::test]
+    async fn notifications_are_pushed_to_every_connection_of_the_recipient() {
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+        let notifier = Notifier::new(pool);
+        let (recipient, bystander) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut first = notifier.subscribe(recipient);
+        let mut second = notifier.subscribe(recipient);
+        let mut other = notifier.subscribe(bystander);
+        let by = User::new().await;
+        let notification = || {
+            let (list, task) = (Uuid::new_v4(), Uuid::new_v4());
+            Notification::new(
+                NotificationKind::Assignment,
+                recipient,
+                list,
+                task,
+                by.clone(),
+            )
+        };
+
+        let pushed = notification();
+        notifier.push(pushed.clone());
+        assert_eq!(first.receiver.try_recv().ok(), Some(pushed.clone()));
+        assert_eq!(second.receiver.try_recv().ok(), Some(pushed));
+        drop(second);
+        notifier.push(notification());
+        assert!(first.receiver.try_recv().is_ok());
+        assert!(other.receiver.try_recv().is_err());
+    }
+}
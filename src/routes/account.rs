@@ -0,0 +1,168 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    Json,
+};
+use axum_sessions::extractors::{ReadableSession, WritableSession};
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::{
+    account::{Account, AccountInfo, Credentials},
+    session::{session_account, TodoSessionExt},
+    state::AppState,
+    user::User,
+};
+
+use super::api::{json_body, json_response, schema, status, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/account", get_account, |generator| {
+        json!({
+            "summary": "Returns the account the session is logged into",
+            "responses": {
+                "200": json_response("The session's account", schema::<AccountInfo>(generator)),
+                "401": status("No session"),
+                "404": status("Anonymous session"),
+            },
+        })
+    })
+    .post("/account", register, |generator| {
+        json!({
+            "summary": "Registers the session's user, and the lists they joined, as an account",
+            "requestBody": json_body(schema::<Credentials>(generator)),
+            "responses": {
+                "201": json_response("The new account", schema::<AccountInfo>(generator)),
+                "401": status("No session"),
+                "409": status("Username taken, or session already registered"),
+                "422": status("Invalid username or password"),
+            },
+        })
+    })
+    .post("/account/login", login, |generator| {
+        json!({
+            "summary": "Logs the session into an account, adding the lists it joined to the account's",
+            "description": "Time entries, templates and notifications of an anonymous session \
+                aren't added to the account, and can't be reached once logged in.",
+            "requestBody": json_body(schema::<Credentials>(generator)),
+            "responses": {
+                "200": json_response("The account logged into", schema::<AccountInfo>(generator)),
+                "401": status("Wrong username or password"),
+                "429": status("Too many failed attempts to log into the account from this address"),
+            },
+        })
+    })
+    .post("/account/logout", logout, |_| {
+        json!({
+            "summary": "Ends the session",
+            "responses": { "204": status("Logged out") },
+        })
+    })
+}
+
+/// Turns the session's anonymous user, along with the lists they joined, into an account.
+#[tracing::instrument(skip_all, name = "Register")]
+async fn register(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    Json(credentials): Json<Credentials>,
+) -> Result<(StatusCode, Json<AccountInfo>), StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if session_account(&session).is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+    if !credentials.is_valid() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let account = Account::register(credentials, user.clone(), state.redis_pool())
+        .await
+        .expect("Failed to register account")
+        .ok_or(StatusCode::CONFLICT)?;
+    Account::store_joined_lists(*user.id(), &session.joined_todo_lists(), state.redis_pool())
+        .await
+        .expect("Failed to store joined lists");
+    let info = account.info();
+    session.regenerate();
+    if session.insert("account", &info.username).is_err() {
+        tracing::error!("Failed to serialize account");
+    }
+
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn get_account(session: ReadableSession) -> Result<Json<AccountInfo>, StatusCode> {
+    let username = session_account(&session).ok_or(StatusCode::NOT_FOUND)?;
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok(Json(AccountInfo { username, user }))
+}
+
+/// Attaches the session to an account. Lists joined anonymously beforehand are added to the
+/// account's. Time entries, templates and notifications of the anonymous user aren't: they
+/// are discarded along with the session. Time entries are also recorded by each list under
+/// that user, so handing them over would mean rewriting the lists' logs.
+/// Failed attempts are limited per account and client address.
+#[tracing::instrument(skip_all, name = "Login")]
+async fn login(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(credentials): Json<Credentials>,
+) -> Result<Json<AccountInfo>, StatusCode> {
+    let username = credentials.username.clone();
+    let allowed = state
+        .login_limiter()
+        .try_attempt(peer.ip(), &username, state.redis_pool())
+        .await
+        .expect("Failed to count login attempt");
+    if !allowed {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+    let account = Account::login(credentials, state.redis_pool())
+        .await
+        .expect("Failed to log in")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    state
+        .login_limiter()
+        .reset(peer.ip(), &username, state.redis_pool())
+        .await
+        .expect("Failed to reset login attempts");
+    let user = account.user();
+    let anonymous_lists = match session_account(&session) {
+        Some(_) => vec![],
+        None => session.joined_todo_lists(),
+    };
+    let joined_lists = Account::update_joined_lists(*user.id(), state.redis_pool(), |lists| {
+        for list in &anonymous_lists {
+            if !lists
+                .iter()
+                .any(|joined| joined.info().id() == list.info().id())
+            {
+                lists.push(list.clone());
+            }
+        }
+        lists.clone()
+    })
+    .await
+    .expect("Failed to update joined lists");
+
+    let info = account.info();
+    session.regenerate();
+    if session.insert("user", user).is_err() || session.insert("account", &info.username).is_err() {
+        tracing::error!("Failed to serialize account");
+    }
+    session.set_joined_todo_lists(joined_lists);
+
+    Ok(Json(info))
+}
+
+/// Ends the session. The next one starts anonymous.
+async fn logout(mut session: WritableSession) -> StatusCode {
+    session.destroy();
+    StatusCode::NO_CONTENT
+}
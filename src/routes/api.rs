@@ -0,0 +1,189 @@
+use axum::{
+    handler::Handler,
+    routing::{self, MethodRouter},
+    Router,
+};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::state::AppState;
+
+/// Routes along with their OpenAPI operations. Routes can only be added along with their
+/// operation, so the OpenAPI document covers every route of the router.
+pub struct ApiRouter {
+    router: Router<AppState>,
+    generator: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl ApiRouter {
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            generator: SchemaSettings::openapi3().into_generator(),
+            paths: Map::new(),
+        }
+    }
+
+    /// Adds the routes of a module.
+    pub fn merge(self, routes: impl FnOnce(Self) -> Self) -> Self {
+        routes(self)
+    }
+
+    pub fn get<H, T>(
+        self,
+        path: &str,
+        handler: H,
+        operation: impl FnOnce(&mut SchemaGenerator) -> Value,
+    ) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        self.route(path, "get", routing::get(handler), operation)
+    }
+
+    pub fn post<H, T>(
+        self,
+        path: &str,
+        handler: H,
+        operation: impl FnOnce(&mut SchemaGenerator) -> Value,
+    ) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        self.route(path, "post", routing::post(handler), operation)
+    }
+
+    pub fn put<H, T>(
+        self,
+        path: &str,
+        handler: H,
+        operation: impl FnOnce(&mut SchemaGenerator) -> Value,
+    ) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        self.route(path, "put", routing::put(handler), operation)
+    }
+
+    pub fn delete<H, T>(
+        self,
+        path: &str,
+        handler: H,
+        operation: impl FnOnce(&mut SchemaGenerator) -> Value,
+    ) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        self.route(path, "delete", routing::delete(handler), operation)
+    }
+
+    fn route(
+        mut self,
+        path: &str,
+        method: &str,
+        method_router: MethodRouter<AppState>,
+        operation: impl FnOnce(&mut SchemaGenerator) -> Value,
+    ) -> Self {
+        let mut operation = operation(&mut self.generator);
+        let has_parameters = operation.get("parameters").is_some();
+        let has_body = operation["requestBody"]["required"] == true;
+        // Requests the extractors reject before they reach the handler
+        if let Some(responses) = operation["responses"].as_object_mut() {
+            if has_parameters || has_body {
+                responses
+                    .entry("400")
+                    .or_insert_with(|| status("Malformed request"));
+            }
+            if has_body {
+                responses
+                    .entry("415")
+                    .or_insert_with(|| status("Body isn't JSON"));
+                responses
+                    .entry("422")
+                    .or_insert_with(|| status("Body doesn't match its schema"));
+            }
+        }
+        let path_item = self
+            .paths
+            .entry(openapi_path(path))
+            .or_insert_with(|| json!({}));
+        path_item[method] = operation;
+        // Methods routed separately on the same path are merged by axum
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    pub fn into_router(self) -> Router<AppState> {
+        self.router
+    }
+
+    /// OpenAPI document of the routes.
+    pub fn into_openapi(mut self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": { "title": "coodo", "version": env!("CARGO_PKG_VERSION") },
+            "paths": self.paths,
+            "components": { "schemas": definitions(&mut self.generator) },
+        })
+    }
+}
+
+/// Turns axum's `:param` path segments into OpenAPI's `{param}`.
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(param) => format!("{{{param}}}"),
+            None => segment.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    serde_json::to_value(generator.subschema_for::<T>()).expect("Failed to serialize schema")
+}
+
+pub fn definitions(generator: &mut SchemaGenerator) -> Map<String, Value> {
+    generator
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| {
+            let schema = serde_json::to_value(schema).expect("Failed to serialize schema");
+            (name, schema)
+        })
+        .collect()
+}
+
+pub fn json_body(schema: Value) -> Value {
+    json!({ "required": true, "content": { "application/json": { "schema": schema } } })
+}
+
+pub fn json_response(description: &str, schema: Value) -> Value {
+    json!({ "description": description, "content": { "application/json": { "schema": schema } } })
+}
+
+pub fn status(description: &str) -> Value {
+    json!({ "description": description })
+}
+
+pub fn path_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "description": description, "schema": schema })
+}
+
+pub fn query_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+/// The `:id` of routes under `/todos`.
+pub fn todo_id(generator: &mut SchemaGenerator) -> Value {
+    path_param("id", "Id of the todo list", schema::<Uuid>(generator))
+}
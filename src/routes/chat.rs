@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use axum_sessions::extractors::ReadableSession;
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{state::AppState, todo::ChatPage, user::User};
+
+use super::api::{json_response, query_param, schema, status, todo_id, ApiRouter};
+
+/// Most messages returned at once.
+const MAX_PAGE_SIZE: usize = 100;
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/todos/:id/chat", get_chat_history, |generator| {
+        json!({
+            "summary": "Pages through the list's chat, from the latest messages backwards",
+            "parameters": [
+                todo_id(generator),
+                query_param("before", "Only return messages sent before this time", schema::<DateTime<Utc>>(generator)),
+                query_param("limit", "Most messages to return, up to 100", json!({ "type": "integer", "default": 50 })),
+            ],
+            "responses": {
+                "200": json_response("Messages, oldest first", schema::<ChatPage>(generator)),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatHistoryParams {
+    /// When the oldest message the client has was sent.
+    before: Option<DateTime<Utc>>,
+    #[serde(default = "default_page_size")]
+    limit: usize,
+}
+
+const fn default_page_size() -> usize {
+    50
+}
+
+/// Pages through a list's chat, from the latest messages backwards.
+async fn get_chat_history(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+    Query(params): Query<ChatHistoryParams>,
+) -> Result<Json<ChatPage>, StatusCode> {
+    let _user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    state
+        .get_todo_list(todo_id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let page = state
+        .chat(todo_id)
+        .page(params.before, params.limit.min(MAX_PAGE_SIZE))
+        .await
+        .expect("Failed to retrieve chat history");
+
+    Ok(Json(page))
+}
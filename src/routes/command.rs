@@ -0,0 +1,354 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_sessions::extractors::ReadableSession;
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    state::AppState,
+    todo::{
+        Command, CommandError, CommandReply, CommandRequest, OfflineOrigin, TodoCommand,
+        TodoCommandSender, TodoList,
+    },
+    user::User,
+};
+
+use super::api::{json_body, json_response, schema, status, todo_id, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.post("/todos/:id/commands", apply_commands, |generator| {
+        json!({
+            "summary": "Applies one or more commands, in order, stopping at the first rejected one",
+            "parameters": [todo_id(generator)],
+            "requestBody": json_body(schema::<CommandBatch>(generator)),
+            "responses": {
+                "200": json_response("All commands have been applied", schema::<CommandsApplied>(generator)),
+                "401": status("No session"),
+                "404": status("No such list"),
+                "409": json_response("A command was based on a stale revision, or edits a task claimed by someone else", schema::<CommandRejected>(generator)),
+                "422": json_response("A command has been rejected", schema::<CommandRejected>(generator)),
+                "503": json_response("A command couldn't be carried out and may be retried", schema::<CommandRejected>(generator)),
+            },
+        })
+    })
+    .post("/todos/:id/merge", merge_offline_commands, |generator| {
+        json!({
+            "summary": "Merges commands made offline since the given revision",
+            "description": "Commands are merged in the order they are listed, rejected ones \
+                don't prevent the following ones from being merged. Commands to what didn't \
+                change are applied, text edits are rebased on concurrent edits, and commands \
+                setting a value changed online after they were issued are rejected. Text \
+                edits to tasks changed online can't be rebased once the list has been \
+                unloaded, which happens when nobody is connected to it, and are then \
+                rejected as conflicting.",
+            "parameters": [todo_id(generator)],
+            "requestBody": json_body(schema::<OfflineBatch>(generator)),
+            "responses": {
+                "200": json_response("Outcome of each command", schema::<MergeReport>(generator)),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum CommandBatch {
+    Single(CommandRequest),
+    Batch(Vec<CommandRequest>),
+}
+
+impl CommandBatch {
+    fn into_vec(self) -> Vec<CommandRequest> {
+        match self {
+            CommandBatch::Single(request) => vec![request],
+            CommandBatch::Batch(requests) => requests,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct CommandsApplied {
+    revision: u64,
+    /// Tasks whose blockers have all been completed by the commands.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unblocked: Vec<Uuid>,
+    list: TodoList,
+}
+
+/// Returned when a command of the batch has been rejected.
+/// The commands preceding it have been applied.
+#[derive(Debug, Serialize, JsonSchema)]
+struct CommandRejected {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    applied: usize,
+    #[serde(flatten)]
+    error: CommandError,
+}
+
+impl IntoResponse for CommandRejected {
+    fn into_response(self) -> Response {
+        let status = match self.error {
+            CommandError::Conflict { .. } | CommandError::TaskClaimed { .. } => {
+                StatusCode::CONFLICT
+            }
+            CommandError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// Applies commands in order, stopping at the first rejected one.
+async fn apply_commands(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+    Json(batch): Json<CommandBatch>,
+) -> Result<Json<CommandsApplied>, Response> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED.into_response())?;
+    let (ids, commands): (Vec<_>, Vec<_>) = batch
+        .into_vec()
+        .into_iter()
+        .map(|request| {
+            let command = request
+                .command
+                .with_issuer(user.clone())
+                .expecting_revision(request.expected_revision);
+            (request.id, command)
+        })
+        .unzip();
+
+    let (replies, list) = send_commands(&state, todo_id, commands, |reply, _| reply.is_ok())
+        .await
+        .map_err(IntoResponse::into_response)?;
+    state
+        .record_visit(*user.id(), todo_id, list.revision())
+        .await;
+    let unblocked = unblocked_by(&replies);
+    let revision = match replies.into_iter().zip(ids).enumerate().next_back() {
+        Some((applied, (Err(error), id))) => {
+            return Err(CommandRejected { id, applied, error }.into_response())
+        }
+        Some((_, (Ok(applied), _))) => applied.revision,
+        None => list.revision(),
+    };
+
+    Ok(Json(CommandsApplied {
+        revision,
+        unblocked,
+        list,
+    }))
+}
+
+/// Sends commands to a list one after the other, loading the list if nobody is connected
+/// to it. The next command is only sent if `keep_going` accepts the reply to the previous one,
+/// which it is given along with the commands left to send so that it can adapt them.
+/// Returns the replies along with the list as the commands left it.
+async fn send_commands(
+    state: &AppState,
+    todo_id: Uuid,
+    commands: Vec<TodoCommand>,
+    keep_going: impl FnMut(&CommandReply, &mut [TodoCommand]) -> bool,
+) -> Result<(Vec<CommandReply>, TodoList), StatusCode> {
+    let command_tx = state
+        .todo_list_commands(todo_id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let replies = send_in_order(&command_tx, commands, keep_going).await;
+    let list = state.release_todo_list(todo_id).await.map_err(|e| {
+        tracing::error!("Failed to release todo list {todo_id}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let replies = replies.map_err(|e| {
+        tracing::error!("Failed to send commands to todo list {todo_id}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((replies, list))
+}
+
+/// Tasks unblocked by the applied commands.
+fn unblocked_by(replies: &[CommandReply]) -> Vec<Uuid> {
+    replies
+        .iter()
+        .flatten()
+        .flat_map(|applied| applied.unblocked.iter().copied())
+        .collect()
+}
+
+async fn send_in_order(
+    command_tx: &TodoCommandSender,
+    commands: Vec<TodoCommand>,
+    mut keep_going: impl FnMut(&CommandReply, &mut [TodoCommand]) -> bool,
+) -> anyhow::Result<Vec<CommandReply>> {
+    let mut replies = Vec::with_capacity(commands.len());
+    // Popped from the end
+    let mut pending = commands.into_iter().rev().collect::<Vec<_>>();
+    while let Some(command) = pending.pop() {
+        let (command, reply_rx) = command.with_reply();
+        command_tx
+            .send(command)
+            .await
+            .context("Todo list task stopped")?;
+        let reply = reply_rx.await.context("Todo list task stopped")?;
+        let next = keep_going(&reply, &mut pending);
+        replies.push(reply);
+        if !next {
+            break;
+        }
+    }
+
+    Ok(replies)
+}
+
+/// Commands made while offline, uploaded once back online.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct OfflineBatch {
+    /// Revision of the list the client had when going offline.
+    base_revision: u64,
+    commands: Vec<OfflineCommand>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct OfflineCommand {
+    #[serde(default)]
+    id: Option<String>,
+    /// Id the client gave to the task created by a `create_task` command. The following
+    /// commands of the batch referring to it are merged into the task created by the server.
+    #[serde(default)]
+    local_task: Option<Uuid>,
+    /// When the command was made, according to the client's clock.
+    issued_at: DateTime<Utc>,
+    #[serde(flatten)]
+    command: Command,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct MergeReport {
+    revision: u64,
+    /// Tasks whose blockers have all been completed by the merged commands.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unblocked: Vec<Uuid>,
+    /// Outcome of each command, in the order they have been merged.
+    results: Vec<MergeResult>,
+    list: TodoList,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct MergeResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Task created by a `create_task` command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_task: Option<Uuid>,
+    #[serde(flatten)]
+    outcome: MergeOutcome,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+enum MergeOutcome {
+    Applied {
+        revision: u64,
+    },
+    /// Applied after having been rebased on changes made in the meantime.
+    Transformed {
+        revision: u64,
+    },
+    Rejected {
+        #[serde(flatten)]
+        error: CommandError,
+    },
+}
+
+/// Merges commands made offline into the list, in the order the client sent them.
+/// Clocks of clients can't be trusted to order them.
+/// Unlike [`apply_commands`], rejected commands don't prevent the following ones
+/// from being merged. See [`Command::rebase`] for the merge rules.
+async fn merge_offline_commands(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+    Json(batch): Json<OfflineBatch>,
+) -> Result<Json<MergeReport>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let (ids, local_tasks): (Vec<_>, Vec<_>) = batch
+        .commands
+        .iter()
+        .map(|offline| (offline.id.clone(), offline.local_task))
+        .unzip();
+    let commands = batch
+        .commands
+        .into_iter()
+        .map(|offline| {
+            let origin = OfflineOrigin {
+                base_revision: batch.base_revision,
+                issued_at: offline.issued_at,
+            };
+            offline
+                .command
+                .with_issuer(user.clone())
+                .made_offline(origin)
+        })
+        .collect();
+
+    let mut local_tasks = local_tasks.into_iter();
+    let (replies, list) = send_commands(&state, todo_id, commands, |reply, pending| {
+        if let (Some(Some(local)), Ok(applied)) = (local_tasks.next(), reply) {
+            if let Some(created) = applied.created {
+                for command in pending {
+                    command.command.replace_task(local, created);
+                }
+            }
+        }
+        true
+    })
+    .await?;
+    let unblocked = unblocked_by(&replies);
+    let results = replies
+        .into_iter()
+        .zip(ids)
+        .map(|(reply, id)| {
+            let created_task = reply.as_ref().ok().and_then(|applied| applied.created);
+            let outcome = match reply {
+                Ok(applied) if applied.transformed => MergeOutcome::Transformed {
+                    revision: applied.revision,
+                },
+                Ok(applied) => MergeOutcome::Applied {
+                    revision: applied.revision,
+                },
+                Err(error) => MergeOutcome::Rejected { error },
+            };
+            MergeResult {
+                id,
+                created_task,
+                outcome,
+            }
+        })
+        .collect();
+    state
+        .record_visit(*user.id(), todo_id, list.revision())
+        .await;
+
+    Ok(Json(MergeReport {
+        revision: list.revision(),
+        unblocked,
+        results,
+        list,
+    }))
+}
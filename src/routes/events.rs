@@ -0,0 +1,278 @@
+use std::{borrow::Cow, collections::VecDeque, convert::Infallible};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Response, Sse,
+    },
+};
+use axum_sessions::extractors::WritableSession;
+use futures_util::{stream, Stream};
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::{
+    message::ServerMessage,
+    notification::NotificationReceiver,
+    session::{update_joined_lists, TodoSessionExt},
+    state::AppState,
+    todo::{TodoListConnection, TodoListFollower, TodoListUpdate},
+    user::User,
+};
+
+use super::api::{query_param, status, todo_id, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/todos/:id/events", todo_list_events, |generator| {
+        json!({
+            "summary": "Streams updates of a todo list as Server-Sent Events",
+            "description": "Each event's data is a `ServerMessage` from `/schema/ws.json`. \
+                Snapshots and patches have the list's revision as id.",
+            "parameters": [
+                todo_id(generator),
+                query_param("presence", "Receive connected users whenever they change", json!({ "type": "boolean" })),
+                {
+                    "name": "Last-Event-ID",
+                    "in": "header",
+                    "description": "Last revision seen, to only receive missed changes",
+                    "schema": { "type": "integer", "minimum": 0 },
+                },
+            ],
+            "responses": {
+                "200": { "description": "Event stream", "content": { "text/event-stream": {} } },
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct EventsParams {
+    /// Push who is connected to the list whenever it changes.
+    presence: bool,
+}
+
+/// Streams the same messages as the WebSocket connection, as Server-Sent Events.
+/// Snapshots and patches carry the list's revision as their id, so that browsers resume
+/// from where they left off through `Last-Event-ID` when reconnecting.
+#[tracing::instrument(
+    name = "TodoList events"
+    skip_all,
+    fields(todo = %todo_id)
+)]
+async fn todo_list_events(
+    mut session: WritableSession,
+    Path(todo_id): Path<Uuid>,
+    Query(params): Query<EventsParams>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let Some(user) = session.get::<User>("user") else {
+        return (StatusCode::UNAUTHORIZED, "Establish a session first").into_response();
+    };
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.parse::<u64>().ok());
+
+    let Ok(connection) = state.join_todo_list(todo_id, *user.id()).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(e) = update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.join_todo_list(&connection.todo.borrow())
+    })
+    .await
+    {
+        tracing::error!("Failed to update lists joined by user {}: {e:?}", user.id());
+    }
+    let revision = connection.todo.borrow().revision();
+    state.record_visit(*user.id(), todo_id, revision).await;
+    if let Err(e) = connection.announce(&user).await {
+        tracing::error!(
+            "User {} failed to join todo list {todo_id}: {e:?}",
+            user.id()
+        );
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let mut events = EventStream {
+        notifications: state.notifier().subscribe(*user.id()),
+        presence: Presence {
+            state: state.clone(),
+            todo: todo_id,
+            user: user.clone(),
+            connection: Some(connection),
+        },
+        follower: TodoListFollower::new(true),
+        pending: VecDeque::from([message_event(&ServerMessage::hello(user))]),
+        watch_presence: params.presence,
+    };
+    events.catch_up(last_event_id);
+    if params.presence {
+        events.queue_presence();
+    }
+
+    Sse::new(events.into_stream())
+        .keep_alive(KeepAlive::new().interval(state.heartbeat_interval()))
+        .into_response()
+}
+
+struct EventStream {
+    notifications: NotificationReceiver,
+    presence: Presence,
+    follower: TodoListFollower,
+    pending: VecDeque<Event>,
+    watch_presence: bool,
+}
+
+impl EventStream {
+    /// Queues the changes missed since `last_event_id` if they are still available,
+    /// or a snapshot of the list otherwise.
+    fn catch_up(&mut self, last_event_id: Option<u64>) {
+        let connection = self.presence.connection_mut();
+        let missed = {
+            let todo_list = connection.todo.borrow();
+            last_event_id.and_then(|since| connection.history.since(since, todo_list.revision()))
+        };
+        match missed {
+            Some(missed) => {
+                self.follower.sent(connection.todo.borrow().revision());
+                self.pending.extend(missed.into_iter().filter_map(|update| {
+                    let TodoListUpdate::Patch { revision, ops } = update else {
+                        return None;
+                    };
+                    Some(update_event(
+                        &ServerMessage::Patch { revision, ops },
+                        revision,
+                    ))
+                }));
+            }
+            None => self.queue_snapshot(),
+        }
+    }
+
+    fn queue_snapshot(&mut self) {
+        let todo_list = self.presence.connection_mut().todo.borrow_and_update();
+        let revision = todo_list.revision();
+        self.follower.sent(revision);
+        self.pending.push_back(update_event(
+            &ServerMessage::Snapshot(Cow::Borrowed(&todo_list)),
+            revision,
+        ));
+    }
+
+    fn queue_presence(&mut self) {
+        let presence = self
+            .presence
+            .connection_mut()
+            .presence
+            .borrow_and_update()
+            .clone();
+        self.pending
+            .push_back(message_event(&ServerMessage::Presence(presence)));
+    }
+
+    async fn next_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            let connection = self.presence.connection_mut();
+            tokio::select! {
+                change = connection.changes.recv() => {
+                    let change = match change {
+                        Ok(change) => change,
+                        // Skipped changes are caught up on with a snapshot
+                        Err(RecvError::Lagged(_)) => {
+                            self.queue_snapshot();
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    };
+                    match self.follower.follow(&change) {
+                        Some(TodoListUpdate::Snapshot) => self.queue_snapshot(),
+                        Some(TodoListUpdate::Patch { revision, ops }) => self.pending.push_back(
+                            update_event(&ServerMessage::Patch { revision, ops }, revision),
+                        ),
+                        None => (),
+                    }
+                    if !change.unblocked.is_empty() {
+                        let unblocked = ServerMessage::Unblocked {
+                            revision: change.revision,
+                            tasks: change.unblocked,
+                        };
+                        self.pending.push_back(message_event(&unblocked));
+                    }
+                },
+                Ok(()) = connection.presence.changed(), if self.watch_presence => {
+                    self.queue_presence();
+                },
+                Ok(event) = connection.chat.recv() => {
+                    self.pending.push_back(message_event(&ServerMessage::Chat(event)));
+                },
+                Some(notification) = self.notifications.recv() => {
+                    self.pending
+                        .push_back(message_event(&ServerMessage::Notification(notification)));
+                },
+            }
+        }
+    }
+
+    fn into_stream(self) -> impl Stream<Item = Result<Event, Infallible>> {
+        stream::unfold(self, |mut events| async move {
+            let event = events.next_event().await?;
+            Some((Ok(event), events))
+        })
+    }
+}
+
+fn update_event(message: &ServerMessage, revision: u64) -> Event {
+    message_event(message).id(revision.to_string())
+}
+
+fn message_event(message: &ServerMessage) -> Event {
+    Event::default()
+        .json_data(message)
+        .expect("Failed to serialize message")
+}
+
+/// Keeps the user in the list for as long as the stream is alive.
+struct Presence {
+    state: AppState,
+    todo: Uuid,
+    user: User,
+    connection: Option<TodoListConnection>,
+}
+
+impl Presence {
+    fn connection_mut(&mut self) -> &mut TodoListConnection {
+        self.connection
+            .as_mut()
+            .expect("Connection is only taken on drop")
+    }
+}
+
+impl Drop for Presence {
+    fn drop(&mut self) {
+        let Some(connection) = self.connection.take() else {
+            return;
+        };
+        tracing::debug!("Event stream closed");
+        let (state, todo, user) = (self.state.clone(), self.todo, *self.user.id());
+        let revision = connection.todo.borrow().revision();
+        tokio::spawn(async move { state.record_visit(user, todo, revision).await });
+        self.state.schedule_leave(
+            self.todo,
+            *self.user.id(),
+            connection.id,
+            connection.presence_tx,
+        );
+    }
+}
@@ -1,10 +1,44 @@
 use axum::Router;
+use serde_json::Value;
 
 use crate::state::AppState;
 
+use self::api::ApiRouter;
+
+pub use schema::ws_schema;
+
+mod account;
+mod api;
+mod chat;
+mod command;
+mod events;
+mod notification;
+mod schema;
 mod session;
+mod template;
+mod time;
 mod todo;
 
+/// Every route, along with its OpenAPI operation.
+fn api() -> ApiRouter {
+    ApiRouter::new()
+        .merge(account::routes)
+        .merge(chat::routes)
+        .merge(command::routes)
+        .merge(events::routes)
+        .merge(notification::routes)
+        .merge(schema::routes)
+        .merge(session::routes)
+        .merge(template::routes)
+        .merge(time::routes)
+        .merge(todo::routes)
+}
+
 pub fn router() -> Router<AppState> {
-    Router::new().merge(session::routes()).merge(todo::routes())
+    api().into_router()
+}
+
+/// OpenAPI document of the HTTP routes.
+pub fn openapi() -> Value {
+    api().into_openapi()
 }
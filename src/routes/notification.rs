@@ -0,0 +1,106 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use axum_sessions::extractors::ReadableSession;
+use hyper::StatusCode;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{notification::Inbox, state::AppState, user::User};
+
+use super::api::{json_response, path_param, schema, status, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/notifications", get_notifications, |generator| {
+        json!({
+            "summary": "Returns the user's notifications, most recent first",
+            "responses": {
+                "200": json_response("The user's inbox", schema::<Inbox>(generator)),
+                "401": status("No session"),
+            },
+        })
+    })
+    .get("/notifications/unread", get_unread_count, |generator| {
+        json!({
+            "summary": "Counts the user's unread notifications",
+            "responses": {
+                "200": json_response("Unread notifications", schema::<usize>(generator)),
+                "401": status("No session"),
+            },
+        })
+    })
+    .post("/notifications/read", mark_all_read, |_| {
+        json!({
+            "summary": "Marks all the user's notifications as read",
+            "responses": {
+                "204": status("Notifications marked as read"),
+                "401": status("No session"),
+            },
+        })
+    })
+    .post("/notifications/:id/read", mark_read, |generator| {
+        json!({
+            "summary": "Marks a notification as read",
+            "parameters": [path_param("id", "Id of the notification", schema::<Uuid>(generator))],
+            "responses": {
+                "204": status("Notification marked as read"),
+                "401": status("No session"),
+            },
+        })
+    })
+}
+
+async fn get_notifications(
+    session: ReadableSession,
+    State(state): State<AppState>,
+) -> Result<Json<Inbox>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let inbox = Inbox::from_redis(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to retrieve notifications");
+
+    Ok(Json(inbox))
+}
+
+async fn get_unread_count(
+    session: ReadableSession,
+    State(state): State<AppState>,
+) -> Result<Json<usize>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let unread = Inbox::unread_count(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to count unread notifications");
+
+    Ok(Json(unread))
+}
+
+async fn mark_read(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(notification): Path<Uuid>,
+) -> StatusCode {
+    let Some(user) = session.get::<User>("user") else {
+        return StatusCode::UNAUTHORIZED;
+    };
+    Inbox::mark_read(*user.id(), notification, state.redis_pool())
+        .await
+        .expect("Failed to mark notification as read");
+
+    StatusCode::NO_CONTENT
+}
+
+async fn mark_all_read(session: ReadableSession, State(state): State<AppState>) -> StatusCode {
+    let Some(user) = session.get::<User>("user") else {
+        return StatusCode::UNAUTHORIZED;
+    };
+    Inbox::mark_all_read(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to mark notifications as read");
+
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,60 @@
+use axum::Json;
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Value};
+
+use crate::{
+    message::ServerMessage,
+    todo::{ChatRequest, CommandRequest},
+};
+
+use super::{
+    api::{definitions, json_response, schema, ApiRouter},
+    openapi,
+    todo::{ClientPing, ClientPresence},
+};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get(
+        "/openapi.json",
+        || async { Json(openapi()) },
+        |_| {
+            json!({
+                "summary": "Returns this document",
+                "responses": { "200": json_response("OpenAPI document", json!({ "type": "object" })) },
+            })
+        },
+    )
+    .get(
+        "/schema/ws.json",
+        || async { Json(ws_schema()) },
+        |_| {
+            json!({
+                "summary": "Returns the JSON Schema of real-time messages",
+                "responses": { "200": json_response("JSON Schema", json!({ "type": "object" })) },
+            })
+        },
+    )
+}
+
+/// JSON Schema of the messages exchanged over `GET /todos/:id` and `GET /todos/:id/events`.
+pub fn ws_schema() -> Value {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let command = schema::<CommandRequest>(&mut generator);
+    let ping = schema::<ClientPing>(&mut generator);
+    let presence = schema::<ClientPresence>(&mut generator);
+    let chat = schema::<ChatRequest>(&mut generator);
+    let server_message = schema::<ServerMessage>(&mut generator);
+    let mut definitions = definitions(&mut generator);
+    definitions.insert(
+        "ClientMessage".to_owned(),
+        json!({ "anyOf": [command, ping, presence, chat] }),
+    );
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "coodo real-time messages",
+        "description": "Clients send `ClientMessage`s, the server pushes `ServerMessage`s.",
+        "definitions": definitions,
+        "anyOf": [{ "$ref": "#/definitions/ClientMessage" }, server_message],
+    })
+}
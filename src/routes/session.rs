@@ -1,22 +1,33 @@
-use axum::{Json, Router};
+use axum::{extract::State, Json};
 use axum_sessions::extractors::WritableSession;
 use chrono::Duration;
+use serde_json::json;
 
 use crate::{state::AppState, user::User};
 
-pub fn routes() -> Router<AppState> {
-    use axum::routing::get;
+use super::api::{json_response, schema, ApiRouter};
 
-    Router::new().route("/session", get(get_session))
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/session", get_session, |generator| {
+        json!({
+            "summary": "Returns the session's user, creating both if needed",
+            "responses": { "200": json_response("The session's user", schema::<User>(generator)) },
+        })
+    })
 }
 
 #[tracing::instrument(skip_all, ret, name = "Get session")]
-async fn get_session(mut session: WritableSession) -> Json<User> {
+async fn get_session(mut session: WritableSession, State(state): State<AppState>) -> Json<User> {
     if session.is_expired() {
         session.destroy();
     }
 
-    let user = session.get::<User>("user").unwrap_or(User::new().await);
+    let user = match session.get::<User>("user") {
+        Some(user) => user,
+        None => User::create(state.redis_pool())
+            .await
+            .expect("Failed to create user"),
+    };
     if session.insert("user", &user).is_err() {
         tracing::error!("Failed to serialize user");
     }
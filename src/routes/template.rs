@@ -0,0 +1,112 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use axum_sessions::extractors::ReadableSession;
+use hyper::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{state::AppState, todo::TodoTemplate, user::User};
+
+use super::api::{json_body, json_response, path_param, schema, status, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.post("/templates", save_template, |generator| {
+        json!({
+            "summary": "Saves a todo list as a template",
+            "requestBody": json_body(schema::<SaveTemplateRequest>(generator)),
+            "responses": {
+                "201": status("Template saved"),
+                "401": status("No session"),
+                "404": status("No such list"),
+                "422": status("Empty template name"),
+            },
+        })
+    })
+    .get("/templates", get_templates, |generator| {
+        json!({
+            "summary": "Lists the names of the user's templates",
+            "responses": {
+                "200": json_response("Template names", schema::<Vec<String>>(generator)),
+                "401": status("No session"),
+            },
+        })
+    })
+    .delete("/templates/:name", delete_template, |_| {
+        json!({
+            "summary": "Deletes a template",
+            "parameters": [path_param("name", "Name of the template", json!({ "type": "string" }))],
+            "responses": {
+                "204": status("Template deleted"),
+                "401": status("No session"),
+                "404": status("No such template"),
+            },
+        })
+    })
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SaveTemplateRequest {
+    list: Uuid,
+    name: String,
+}
+
+async fn save_template(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Json(request): Json<SaveTemplateRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if request.name.trim().is_empty() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let todo_list = state
+        .get_todo_list(request.list)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    TodoTemplate::from_list(request.name, &todo_list)
+        .store(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to store template");
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn get_templates(
+    session: ReadableSession,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let mut names = TodoTemplate::names(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to retrieve templates");
+    names.sort();
+
+    Ok(Json(names))
+}
+
+async fn delete_template(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    let Some(user) = session.get::<User>("user") else {
+        return StatusCode::UNAUTHORIZED;
+    };
+    let removed = TodoTemplate::delete(*user.id(), &name, state.redis_pool())
+        .await
+        .expect("Failed to delete template");
+
+    if removed {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -0,0 +1,114 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use axum_sessions::extractors::ReadableSession;
+use chrono::{DateTime, Duration, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    state::AppState,
+    todo::{ListTimeReport, TimeEntry, UserTimeReport},
+    user::User,
+};
+
+use super::api::{json_response, query_param, schema, status, todo_id, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/todos/:id/time", get_todo_list_time, |generator| {
+        json!({
+            "summary": "Returns the time tracked on a todo list",
+            "parameters": [todo_id(generator)],
+            "responses": {
+                "200": json_response("Time report", schema::<ListTimeReport>(generator)),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+    .get("/time/running", get_running_timer, |generator| {
+        json!({
+            "summary": "Returns the user's running timer",
+            "responses": {
+                "200": json_response("Running timer, if any", schema::<Option<TimeEntry>>(generator)),
+                "401": status("No session"),
+            },
+        })
+    })
+    .get("/time/report", get_time_report, |generator| {
+        json!({
+            "summary": "Returns the time tracked by the user, by default over the last 7 days",
+            "parameters": [
+                query_param("from", "Start of the report", json!({ "type": "string", "format": "date-time" })),
+                query_param("to", "End of the report", json!({ "type": "string", "format": "date-time" })),
+            ],
+            "responses": {
+                "200": json_response("Time report", schema::<UserTimeReport>(generator)),
+                "400": status("`from` is after `to`, or malformed"),
+                "401": status("No session"),
+            },
+        })
+    })
+}
+
+async fn get_todo_list_time(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+) -> Result<Json<ListTimeReport>, StatusCode> {
+    let _user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let todo_list = state
+        .get_todo_list(todo_id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let entries = TimeEntry::list_entries(todo_id, state.redis_pool())
+        .await
+        .expect("Failed to retrieve time entries");
+
+    Ok(Json(ListTimeReport::new(&todo_list, &entries)))
+}
+
+async fn get_running_timer(
+    session: ReadableSession,
+    State(state): State<AppState>,
+) -> Result<Json<Option<TimeEntry>>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let running = TimeEntry::running_timer(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to retrieve running timer");
+
+    Ok(Json(running))
+}
+
+#[derive(Debug, Deserialize)]
+struct TimeReportParams {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+async fn get_time_report(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Query(params): Query<TimeReportParams>,
+) -> Result<Json<UserTimeReport>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let to = params.to.unwrap_or_else(Utc::now);
+    let from = params.from.unwrap_or(to - Duration::days(7));
+    if from > to {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let entries = TimeEntry::user_entries(*user.id(), from, to, state.redis_pool())
+        .await
+        .expect("Failed to retrieve time entries");
+
+    Ok(Json(UserTimeReport::new(from, to, entries)))
+}
@@ -1,46 +1,215 @@
+use std::borrow::Cow;
+
 use anyhow::Context;
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        Path, Query, State,
     },
     response::{IntoResponse, Response},
-    routing::{delete, get, post},
-    Json, Router,
+    Json,
 };
 use axum_sessions::extractors::{ReadableSession, WritableSession};
-use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use futures_util::{stream::FuturesUnordered, FutureExt, StreamExt};
 use hyper::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{Instant, MissedTickBehavior},
+};
 use uuid::Uuid;
 
 use crate::{
-    session::TodoSessionExt,
+    message::{NoticeKind, ServerMessage},
+    session::{joined_lists, update_joined_lists, JoinedTodoList, TodoSessionExt},
     state::AppState,
-    todo::{Command, TodoCommandSender, TodoList, TodoListInfo, TodoListWatcher},
+    todo::{
+        ChatRequest, CloneOptions, CommandError, CommandRequest, ListVisit, PresenceState,
+        PresenceUpdate, PresenceWatcher, TodoList, TodoListConnection, TodoListFollower,
+        TodoListHistory, TodoListStats, TodoListUpdate, TodoListWatcher, TodoTemplate,
+    },
     user::User,
+    wire::{WireFormat, WireSink},
 };
 
-pub fn routes() -> Router<AppState> {
-    Router::new()
-        .route("/todos/:id", get(join_todo_list))
-        .route("/todos", post(create_todo_list))
-        .route("/todos", get(get_users_todo_lists))
-        .route("/todos/:id", delete(leave_todo_list))
+use super::api::{json_body, json_response, query_param, schema, status, todo_id, ApiRouter};
+
+pub fn routes(api: ApiRouter) -> ApiRouter {
+    api.get("/todos/:id", join_todo_list, |generator| {
+        json!({
+            "summary": "Connects to a todo list through a WebSocket",
+            "description": "Messages are described by `/schema/ws.json`. \
+                They are encoded according to the `Sec-WebSocket-Protocol` negotiated among \
+                `coodo.v1.json` (default), `coodo.v1.msgpack` and `coodo.v1.cbor`.",
+            "parameters": [
+                todo_id(generator),
+                query_param("patches", "Receive JSON patches instead of full snapshots", json!({ "type": "boolean" })),
+                query_param("stats", "Receive list statistics along with updates", json!({ "type": "boolean" })),
+                query_param("since", "Last revision seen, to only receive missed changes", json!({ "type": "integer", "minimum": 0 })),
+                query_param("presence", "Receive connected users whenever they change", json!({ "type": "boolean" })),
+            ],
+            "responses": {
+                "101": status("Switching to the WebSocket protocol"),
+                "400": status("Not a WebSocket handshake, or malformed"),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+    .post("/todos", create_todo_list, |generator| {
+        json!({
+            "summary": "Creates a todo list and joins it",
+            "parameters": [
+                query_param("template", "Name of the template to create the list from", json!({ "type": "string" })),
+            ],
+            "responses": {
+                "200": json_response("Id of the new list", schema::<Uuid>(generator)),
+                "401": status("No session"),
+                "404": status("No such template"),
+            },
+        })
+    })
+    .get("/todos", get_users_todo_lists, |generator| {
+        json!({
+            "summary": "Lists the todo lists joined by the user, pinned ones first",
+            "responses": {
+                "200": json_response("Joined lists", schema::<Vec<JoinedTodoList>>(generator)),
+                "401": status("No session"),
+            },
+        })
+    })
+    .delete("/todos/:id", leave_todo_list, |generator| {
+        json!({
+            "summary": "Removes a todo list from the joined ones",
+            "parameters": [todo_id(generator)],
+            "responses": { "204": status("List left") },
+        })
+    })
+    .post("/todos/:id/clone", clone_todo_list, |generator| {
+        json!({
+            "summary": "Copies a todo list into a new one owned by the user",
+            "parameters": [todo_id(generator)],
+            "requestBody": { "required": false, "content": { "application/json": { "schema": schema::<CloneOptions>(generator) } } },
+            "responses": {
+                "200": json_response("Id of the new list", schema::<Uuid>(generator)),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+    .get("/todos/:id/stats", get_todo_list_stats, |generator| {
+        json!({
+            "summary": "Returns statistics about a todo list",
+            "parameters": [todo_id(generator)],
+            "responses": {
+                "200": json_response("List statistics", schema::<TodoListStats>(generator)),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+    .put("/todos/:id/pinned", pin_todo_list, |generator| {
+        json!({
+            "summary": "Pins or unpins a joined todo list",
+            "parameters": [todo_id(generator)],
+            "requestBody": json_body(schema::<bool>(generator)),
+            "responses": { "204": status("List (un)pinned"), "404": status("List not joined") },
+        })
+    })
+    .put("/todos/:id/folder", move_todo_list, |generator| {
+        json!({
+            "summary": "Moves a joined todo list to a folder, or out of any",
+            "parameters": [todo_id(generator)],
+            "requestBody": json_body(schema::<Option<String>>(generator)),
+            "responses": { "204": status("List moved"), "404": status("List not joined") },
+        })
+    })
+    .post("/todos/:id/seen", mark_todo_list_seen, |generator| {
+        json!({
+            "summary": "Marks every change made to the list so far as seen",
+            "parameters": [todo_id(generator)],
+            "responses": {
+                "204": status("List marked as seen"),
+                "401": status("No session"),
+                "404": status("No such list"),
+            },
+        })
+    })
+    .put("/todos/order", reorder_todo_lists, |generator| {
+        json!({
+            "summary": "Reorders the joined todo lists",
+            "requestBody": json_body(schema::<Vec<Uuid>>(generator)),
+            "responses": { "204": status("Lists reordered") },
+        })
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTodoListParams {
+    template: Option<String>,
 }
 
 async fn create_todo_list(
     mut session: WritableSession,
     State(state): State<AppState>,
+    Query(params): Query<CreateTodoListParams>,
 ) -> Result<Json<Uuid>, StatusCode> {
-    let _user = session
+    let user = session
         .get::<User>("user")
         .ok_or(StatusCode::UNAUTHORIZED)?;
-    let todo_list = TodoList::default();
-    session.join_todo_list(&todo_list);
+    let todo_list = match params.template {
+        Some(template) => TodoTemplate::from_redis(*user.id(), &template, state.redis_pool())
+            .await
+            .expect("Failed to retrieve template")
+            .ok_or(StatusCode::NOT_FOUND)?
+            .instantiate(&user),
+        None => TodoList::owned_by(user.clone()),
+    };
+    update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.join_todo_list(&todo_list)
+    })
+    .await
+    .expect("Failed to update joined lists");
     todo_list
         .store(state.redis_pool())
         .await
         .expect("Failed to create todo list");
+    state
+        .record_visit(*user.id(), todo_list.id(), todo_list.revision())
+        .await;
+
+    Ok(Json(todo_list.id()))
+}
+
+async fn clone_todo_list(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+    options: Option<Json<CloneOptions>>,
+) -> Result<Json<Uuid>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let Json(options) = options.unwrap_or_default();
+    let todo_list = state
+        .get_todo_list(todo_id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?
+        .duplicate(options, &user);
+    update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.join_todo_list(&todo_list)
+    })
+    .await
+    .expect("Failed to update joined lists");
+    todo_list
+        .store(state.redis_pool())
+        .await
+        .expect("Failed to create todo list");
+    state
+        .record_visit(*user.id(), todo_list.id(), todo_list.revision())
+        .await;
 
     Ok(Json(todo_list.id()))
 }
@@ -48,18 +217,140 @@ async fn create_todo_list(
 async fn get_users_todo_lists(
     session: ReadableSession,
     State(state): State<AppState>,
-) -> Json<Vec<TodoListInfo<'static>>> {
-    let mut joined_lists = session
-        .get::<Vec<TodoListInfo>>("user_lists")
-        .unwrap_or_default();
+) -> Result<Json<Vec<JoinedTodoList>>, StatusCode> {
+    let user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let mut joined_lists = joined_lists(&session, state.redis_pool()).await;
 
-    state.fill_todo_lists_info(&mut joined_lists).await;
+    state
+        .fill_todo_lists_info(joined_lists.iter_mut().map(JoinedTodoList::info_mut))
+        .await;
+    let visits = ListVisit::of_user(*user.id(), state.redis_pool())
+        .await
+        .expect("Failed to retrieve visits");
+    for list in &mut joined_lists {
+        list.set_last_visit(visits.get(&list.info().id()));
+    }
+    joined_lists.sort_by_key(|list| !list.is_pinned());
 
-    Json(joined_lists)
+    Ok(Json(joined_lists))
 }
 
-async fn leave_todo_list(mut session: WritableSession, Path(todo_id): Path<Uuid>) {
-    session.leave_todo_list(todo_id);
+async fn get_todo_list_stats(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+) -> Result<Json<TodoListStats>, StatusCode> {
+    let _user = session
+        .get::<User>("user")
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let todo_list = state
+        .get_todo_list(todo_id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok(Json(TodoListStats::new(&todo_list)))
+}
+
+async fn pin_todo_list(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+    Json(pinned): Json<bool>,
+) -> StatusCode {
+    let pinned = update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.pin_todo_list(todo_id, pinned)
+    })
+    .await
+    .expect("Failed to update joined lists");
+    if pinned {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Marks every change made to the list so far as seen by the user.
+async fn mark_todo_list_seen(
+    session: ReadableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+) -> StatusCode {
+    let Some(user) = session.get::<User>("user") else {
+        return StatusCode::UNAUTHORIZED;
+    };
+    let Ok(todo_list) = state.get_todo_list(todo_id).await else {
+        return StatusCode::NOT_FOUND;
+    };
+    state
+        .record_visit(*user.id(), todo_id, todo_list.revision())
+        .await;
+
+    StatusCode::NO_CONTENT
+}
+
+async fn move_todo_list(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+    Json(folder): Json<Option<String>>,
+) -> StatusCode {
+    let folder = folder
+        .map(|folder| folder.trim().to_owned())
+        .filter(|folder| !folder.is_empty());
+    let moved = update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.move_todo_list(todo_id, folder.clone())
+    })
+    .await
+    .expect("Failed to update joined lists");
+    if moved {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn reorder_todo_lists(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    Json(order): Json<Vec<Uuid>>,
+) -> StatusCode {
+    update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.reorder_todo_lists(&order)
+    })
+    .await
+    .expect("Failed to update joined lists");
+    StatusCode::NO_CONTENT
+}
+
+async fn leave_todo_list(
+    mut session: WritableSession,
+    State(state): State<AppState>,
+    Path(todo_id): Path<Uuid>,
+) -> StatusCode {
+    update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.leave_todo_list(todo_id)
+    })
+    .await
+    .expect("Failed to update joined lists");
+    StatusCode::NO_CONTENT
+}
+
+/// Per-connection options, passed as query parameters when connecting to a list.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConnectionParams {
+    /// Push [`TodoListStats`] along with every list update.
+    stats: bool,
+    /// Receive a snapshot on connect followed by JSON patches instead of the whole list
+    /// on every change.
+    patches: bool,
+    /// Last revision seen by a reconnecting client, which will only be sent the changes
+    /// it missed. Implies `patches`.
+    since: Option<u64>,
+    /// Push who is connected to the list, and what they are up to, whenever it changes.
+    presence: bool,
 }
 
 #[tracing::instrument(
@@ -70,20 +361,28 @@ async fn leave_todo_list(mut session: WritableSession, Path(todo_id): Path<Uuid>
 async fn join_todo_list(
     mut session: WritableSession,
     Path(todo_id): Path<Uuid>,
+    Query(params): Query<ConnectionParams>,
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
 ) -> Response {
     let Some(user) = session.get::<User>("user") else {
         return (StatusCode::UNAUTHORIZED, "Establish a session first").into_response();
     };
-    let (todo_watch, command_tx, abort_rx) = state.join_todo_list(todo_id, *user.id()).await;
-    session.join_todo_list(&todo_watch.borrow());
-
-    ws.on_upgrade(move |socket| {
-        ws_handler(
-            socket, state, todo_id, todo_watch, command_tx, abort_rx, user,
-        )
+    let Ok(connection) = state.join_todo_list(todo_id, *user.id()).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(e) = update_joined_lists(&mut session, state.redis_pool(), |session| {
+        session.join_todo_list(&connection.todo.borrow())
     })
+    .await
+    {
+        tracing::error!("Failed to update lists joined by user {}: {e:?}", user.id());
+    }
+    let revision = connection.todo.borrow().revision();
+    state.record_visit(*user.id(), todo_id, revision).await;
+
+    ws.protocols(WireFormat::PROTOCOLS)
+        .on_upgrade(move |socket| ws_handler(socket, state, todo_id, connection, user, params))
 }
 
 #[tracing::instrument(
@@ -98,67 +397,302 @@ async fn ws_handler(
     ws: WebSocket,
     state: AppState,
     todo_list_id: Uuid,
-    mut todo: TodoListWatcher,
-    command_tx: TodoCommandSender,
-    mut abort_rx: tokio::sync::oneshot::Receiver<()>,
+    mut connection: TodoListConnection,
     user: User,
+    params: ConnectionParams,
 ) {
-    let (mut ws_tx, mut ws_rx) = ws.split();
-    if command_tx
-        .send(Command::UserJoin(user.clone()).with_issuer(user.clone()))
-        .await
-        .is_err()
-    {
+    let format = ws
+        .protocol()
+        .and_then(|protocol| protocol.to_str().ok())
+        .and_then(WireFormat::from_protocol)
+        .unwrap_or_default();
+    let (ws_tx, mut ws_rx) = ws.split();
+    let mut ws_tx = WireSink::new(ws_tx, format);
+    let _ = ws_tx.send(&ServerMessage::hello(user.clone())).await;
+
+    let mut follower = TodoListFollower::new(params.patches || params.since.is_some());
+    let _ = send_initial_update(
+        &mut follower,
+        params.since,
+        &mut connection.todo,
+        &connection.history,
+        &mut ws_tx,
+    )
+    .await;
+    if params.stats {
+        let stats = TodoListStats::new(&connection.todo.borrow());
+        let _ = ws_tx.send(&ServerMessage::Stats(stats)).await;
+    }
+
+    if let Err(e) = connection.announce(&user).await {
         tracing::error!(
-            "User {} failed to join todo list {}",
+            "User {} failed to join todo list {}: {e:?}",
             user.id(),
             todo_list_id
         );
         return;
     }
+    if params.presence {
+        let _ = send_presence(&mut connection.presence, &mut ws_tx).await;
+    }
 
+    let mut notifications = state.notifier().subscribe(*user.id());
+    let mut rate_limiter = state.command_rate_limiter();
+    let mut pending_replies = FuturesUnordered::new();
+    let mut heartbeat = tokio::time::interval_at(
+        Instant::now() + state.heartbeat_interval(),
+        state.heartbeat_interval(),
+    );
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_seen = Instant::now();
     loop {
         tokio::select! {
-            Some(Ok(msg)) = ws_rx.next() => {
-                if matches!(msg, Message::Close(_)) {
-                    break;
+            msg = ws_rx.next() => {
+                let Some(Ok(msg)) = msg else { break };
+                last_seen = Instant::now();
+                let data = match msg {
+                    Message::Text(text) => text.into_bytes(),
+                    Message::Binary(data) => data,
+                    Message::Close(_) => break,
+                    Message::Ping(_) | Message::Pong(_) => continue,
+                };
+                // For clients behind proxies that don't forward ping frames
+                if format.decode::<ClientPing>(&data).is_ok() {
+                    let _ = ws_tx.send(&ServerMessage::Pong).await;
+                    continue;
+                }
+                if let Ok(ClientPresence::Presence(presence)) = format.decode::<ClientPresence>(&data) {
+                    if !rate_limiter.try_acquire() {
+                        let error = ServerMessage::Error { id: None, error: CommandError::RateLimited };
+                        let _ = ws_tx.send(&error).await;
+                        continue;
+                    }
+                    let update = PresenceUpdate::Update { user: *user.id(), state: presence };
+                    let _ = connection.presence_tx.send(update).await;
+                    continue;
+                }
+                if let Ok(request) = format.decode::<ChatRequest>(&data) {
+                    if !rate_limiter.try_acquire() {
+                        let error = ServerMessage::Error { id: request.id, error: CommandError::RateLimited };
+                        let _ = ws_tx.send(&error).await;
+                        continue;
+                    }
+                    match state.chat(todo_list_id).apply(request.command, &user).await {
+                        Ok(Ok(event)) => {
+                            let _ = connection.chat_tx.send(event);
+                        }
+                        Ok(Err(error)) => {
+                            let _ = ws_tx.send(&ServerMessage::Error { id: request.id, error }).await;
+                        }
+                        Err(e) => tracing::error!("Failed to update chat: {e:?}"),
+                    }
+                    continue;
+                }
+                let request = match format.decode::<CommandRequest>(&data) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let error = ServerMessage::Error {
+                            id: request_id(format, &data),
+                            error: CommandError::Malformed { message: e.to_string() },
+                        };
+                        let _ = ws_tx.send(&error).await;
+                        continue;
+                    }
+                };
+                if !rate_limiter.try_acquire() {
+                    let error = ServerMessage::Error {
+                        id: request.id,
+                        error: CommandError::RateLimited,
+                    };
+                    let _ = ws_tx.send(&error).await;
+                    continue;
+                }
+                let (command, reply_rx) = request
+                    .command
+                    .with_issuer(user.clone())
+                    .expecting_revision(request.expected_revision)
+                    .through_connection(connection.id)
+                    .with_reply();
+                if connection.command_tx.send(command).await.is_ok() {
+                    let id = request.id;
+                    pending_replies.push(reply_rx.map(move |reply| (id, reply)));
                 }
-                if let Ok(command) = serde_json::from_slice::<Command>(msg.into_data().as_slice()) {
-                    let _ = command_tx.send(command.with_issuer(user.clone())).await;
+            },
+            Some((id, Ok(reply))) = pending_replies.next() => {
+                let message = match reply {
+                    Ok(applied) => id.map(|id| ServerMessage::Ack {
+                        id,
+                        revision: applied.revision,
+                        unblocked: applied.unblocked,
+                    }),
+                    Err(error) => Some(ServerMessage::Error { id, error }),
+                };
+                if let Some(message) = message {
+                    let _ = ws_tx.send(&message).await;
+                }
+            },
+            change = connection.changes.recv() => {
+                let change = match change {
+                    Ok(change) => change,
+                    // Skipped changes are caught up on with a snapshot
+                    Err(RecvError::Lagged(_)) => {
+                        let _ = send_todo_list(&mut follower, &mut connection.todo, &mut ws_tx).await;
+                        if params.stats {
+                            let stats = TodoListStats::new(&connection.todo.borrow());
+                            let _ = ws_tx.send(&ServerMessage::Stats(stats)).await;
+                        }
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let update = follower.follow(&change);
+                let _ = send_update(update, &mut follower, &mut connection.todo, &mut ws_tx).await;
+                if params.stats {
+                    let _ = ws_tx.send(&ServerMessage::Stats(change.stats)).await;
                 }
+                if !change.unblocked.is_empty() {
+                    let unblocked = ServerMessage::Unblocked {
+                        revision: change.revision,
+                        tasks: change.unblocked,
+                    };
+                    let _ = ws_tx.send(&unblocked).await;
+                }
+            },
+            Ok(()) = connection.presence.changed(), if params.presence => {
+                let _ = send_presence(&mut connection.presence, &mut ws_tx).await;
             },
-            Ok(()) = todo.changed() => {
-                let _ = send_todo_list(&mut todo, &mut ws_tx).await;
+            Ok(event) = connection.chat.recv() => {
+                let _ = ws_tx.send(&ServerMessage::Chat(event)).await;
             },
-            _ = &mut abort_rx => {
-                tracing::debug!("Abort received. Closing previous WS connection");
-                let _ = ws_tx.close().await;
-                return;
+            Some(notification) = notifications.recv() => {
+                let _ = ws_tx.send(&ServerMessage::Notification(notification)).await;
+            },
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > state.idle_timeout() {
+                    tracing::debug!("Connection idle for too long");
+                    let _ = ws_tx.send(&ServerMessage::notice(NoticeKind::IdleTimeout)).await;
+                    break;
+                }
+                let _ = ws_tx.send_raw(Message::Ping(vec![])).await;
             },
             else => break,
         }
     }
 
     let _ = ws_tx.close().await;
-    let _ = command_tx
-        .send(Command::UserLeave(user.clone()).with_issuer(user.clone()))
-        .await;
-    state.leave_todo_list(todo_list_id, *user.id()).await;
     tracing::debug!("WS connection closed");
+
+    let _ = connection
+        .presence_tx
+        .send(PresenceUpdate::Disconnect(connection.id))
+        .await;
+    // Everything up to now has been pushed to the user
+    let revision = connection.todo.borrow().revision();
+    state.record_visit(*user.id(), todo_list_id, revision).await;
+    state.schedule_leave(
+        todo_list_id,
+        *user.id(),
+        connection.id,
+        connection.presence_tx,
+    );
 }
 
+/// Sends the whole list, as it is now.
 async fn send_todo_list(
+    follower: &mut TodoListFollower,
     todo_list: &mut TodoListWatcher,
-    ws_sink: &mut SplitSink<WebSocket, Message>,
+    ws_sink: &mut WireSink,
 ) -> anyhow::Result<()> {
-    let json_msg = {
-        let todo_list = &*todo_list.borrow_and_update();
-        serde_json::to_string(&todo_list).context("Failed to serialize todo list")?
+    let message = {
+        let todo_list = todo_list.borrow_and_update();
+        follower.sent(todo_list.revision());
+        ws_sink
+            .format()
+            .encode(&ServerMessage::Snapshot(Cow::Borrowed(&todo_list)))
+            .context("Failed to serialize todo list")?
     };
-    ws_sink
-        .send(Message::Text(json_msg))
-        .await
-        .context("Failed to send ws message")?;
+    ws_sink.send_raw(message).await
+}
+
+async fn send_presence(
+    presence: &mut PresenceWatcher,
+    ws_sink: &mut WireSink,
+) -> anyhow::Result<()> {
+    let presence = presence.borrow_and_update().clone();
+    ws_sink.send(&ServerMessage::Presence(presence)).await
+}
+
+/// Sends the changes missed since revision `since` if they are still available,
+/// or a snapshot of the list otherwise.
+async fn send_initial_update(
+    follower: &mut TodoListFollower,
+    since: Option<u64>,
+    todo_list: &mut TodoListWatcher,
+    history: &TodoListHistory,
+    ws_sink: &mut WireSink,
+) -> anyhow::Result<()> {
+    let missed = {
+        let todo_list = todo_list.borrow_and_update();
+        let missed = since.and_then(|since| history.since(since, todo_list.revision()));
+        if missed.is_some() {
+            follower.sent(todo_list.revision());
+        }
+        missed
+    };
+    match missed {
+        Some(missed) => {
+            for update in missed {
+                if let TodoListUpdate::Patch { revision, ops } = update {
+                    ws_sink
+                        .send(&ServerMessage::Patch { revision, ops })
+                        .await?;
+                }
+            }
+            Ok(())
+        }
+        None => send_todo_list(follower, todo_list, ws_sink).await,
+    }
+}
+
+/// Sends what `follower` found the client needs, if anything.
+async fn send_update(
+    update: Option<TodoListUpdate>,
+    follower: &mut TodoListFollower,
+    todo_list: &mut TodoListWatcher,
+    ws_sink: &mut WireSink,
+) -> anyhow::Result<()> {
+    match update {
+        Some(TodoListUpdate::Snapshot) => send_todo_list(follower, todo_list, ws_sink).await,
+        Some(TodoListUpdate::Patch { revision, ops }) => {
+            ws_sink.send(&ServerMessage::Patch { revision, ops }).await
+        }
+        None => Ok(()),
+    }
+}
+
+/// Application level ping, answered with [`ServerMessage::Pong`].
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum ClientPing {
+    Ping,
+}
+
+/// Replaces what the user is up to in the list's presence.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub(super) enum ClientPresence {
+    Presence(PresenceState),
+}
+
+/// Best effort extraction of the id of a command that couldn't be parsed.
+fn request_id(format: WireFormat, data: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct RequestId {
+        id: String,
+    }
 
-    Ok(())
+    format
+        .decode::<RequestId>(data)
+        .ok()
+        .map(|request| request.id)
 }
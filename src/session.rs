@@ -7,10 +7,17 @@ use axum_sessions::{
     extractors::WritableSession,
     SessionLayer,
 };
+use chrono::{DateTime, Utc};
 use deadpool_redis::{Connection, Pool, PoolError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::todo::{TodoList, TodoListInfo};
+use crate::{
+    account::Account,
+    todo::{ListVisit, TodoList, TodoListInfo},
+    user::User,
+};
 
 pub fn get_session_layer(redis_pool: Pool) -> SessionLayer<UserSessionStore> {
     use axum_sessions::{PersistencePolicy, SameSite};
@@ -93,7 +100,7 @@ impl SessionStore for UserSessionStore {
             .arg(1)
             .arg(session.id())
             .arg(&serde_json::to_string(&session)?)
-            .query_async(&mut redis)
+            .query_async::<_, ()>(&mut redis)
             .await
             .context("Failed to store session")?;
 
@@ -109,7 +116,7 @@ impl SessionStore for UserSessionStore {
             .arg("session_destroy")
             .arg(1)
             .arg(session.id())
-            .query_async(&mut redis)
+            .query_async::<_, ()>(&mut redis)
             .await?;
 
         Ok(())
@@ -122,39 +129,198 @@ impl SessionStore for UserSessionStore {
         redis::cmd("FCALL")
             .arg("session_clear_all")
             .arg(0)
-            .query_async(&mut redis)
+            .query_async::<_, ()>(&mut redis)
             .await?;
 
         Ok(())
     }
 }
 
+/// A todo list joined by the user, along with how the user organized it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JoinedTodoList {
+    #[serde(flatten)]
+    info: TodoListInfo<'static>,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    folder: Option<String>,
+    /// Changes made to the list since the user last saw it.
+    #[serde(default)]
+    unread_changes: u64,
+    #[serde(default)]
+    changed_since_last_visit: bool,
+    #[serde(default)]
+    last_visited_at: Option<DateTime<Utc>>,
+}
+
+impl JoinedTodoList {
+    pub fn info(&self) -> &TodoListInfo<'static> {
+        &self.info
+    }
+
+    pub fn info_mut(&mut self) -> &mut TodoListInfo<'static> {
+        &mut self.info
+    }
+
+    pub const fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    pub fn folder(&self) -> Option<&str> {
+        self.folder.as_deref()
+    }
+
+    pub const fn unread_changes(&self) -> u64 {
+        self.unread_changes
+    }
+
+    pub const fn changed_since_last_visit(&self) -> bool {
+        self.changed_since_last_visit
+    }
+
+    /// Compares the list's current revision with the one the user last saw.
+    /// Lists never visited are entirely unread.
+    pub fn set_last_visit(&mut self, visit: Option<&ListVisit>) {
+        let seen = visit.map_or(0, |visit| visit.revision);
+        self.unread_changes = self.info.revision().saturating_sub(seen);
+        self.changed_since_last_visit = self.unread_changes > 0;
+        self.last_visited_at = visit.map(|visit| visit.visited_at);
+    }
+}
+
+impl From<TodoListInfo<'_>> for JoinedTodoList {
+    fn from(info: TodoListInfo<'_>) -> Self {
+        Self {
+            info: info.into_owned(),
+            pinned: false,
+            folder: None,
+            unread_changes: 0,
+            changed_since_last_visit: false,
+            last_visited_at: None,
+        }
+    }
+}
+
 pub trait TodoSessionExt {
+    fn joined_todo_lists(&self) -> Vec<JoinedTodoList>;
+    fn set_joined_todo_lists(&mut self, lists: Vec<JoinedTodoList>);
     fn join_todo_list(&mut self, list: &TodoList);
     fn leave_todo_list(&mut self, id: Uuid);
+    /// Returns whether the list has been joined by the user.
+    fn pin_todo_list(&mut self, id: Uuid, pinned: bool) -> bool;
+    /// Returns whether the list has been joined by the user.
+    fn move_todo_list(&mut self, id: Uuid, folder: Option<String>) -> bool;
+    /// Moves the given lists at the top, in the given order.
+    fn reorder_todo_lists(&mut self, order: &[Uuid]);
 }
 
 impl TodoSessionExt for WritableSession {
+    fn joined_todo_lists(&self) -> Vec<JoinedTodoList> {
+        self.get::<Vec<JoinedTodoList>>("user_lists")
+            .unwrap_or_default()
+    }
+
+    fn set_joined_todo_lists(&mut self, lists: Vec<JoinedTodoList>) {
+        store_user_lists(self, lists);
+    }
+
     fn join_todo_list(&mut self, list: &TodoList) {
-        let mut user_lists = self
-            .get::<Vec<TodoListInfo>>("user_lists")
-            .unwrap_or_default();
-
-        if !user_lists.iter().any(|td| td.id() == list.id()) {
-            user_lists.push(list.as_info());
-            if self.insert("user_lists", user_lists).is_err() {
-                tracing::error!("Failed to update user session");
-            }
+        let mut user_lists = self.joined_todo_lists();
+
+        if !user_lists.iter().any(|td| td.info.id() == list.id()) {
+            user_lists.push(list.as_info().into());
+            store_user_lists(self, user_lists);
         }
     }
 
     fn leave_todo_list(&mut self, id: Uuid) {
-        let mut user_lists = self
-            .get::<Vec<TodoListInfo>>("user_lists")
-            .unwrap_or_default();
-        user_lists.retain(|list| list.id() != id);
-        if self.insert("user_lists", user_lists).is_err() {
-            tracing::error!("Failed to update user session");
-        }
+        let mut user_lists = self.joined_todo_lists();
+        user_lists.retain(|list| list.info.id() != id);
+        store_user_lists(self, user_lists);
+    }
+
+    fn pin_todo_list(&mut self, id: Uuid, pinned: bool) -> bool {
+        update_joined_list(self, id, |list| list.pinned = pinned)
+    }
+
+    fn move_todo_list(&mut self, id: Uuid, folder: Option<String>) -> bool {
+        update_joined_list(self, id, |list| list.folder = folder)
+    }
+
+    fn reorder_todo_lists(&mut self, order: &[Uuid]) {
+        let mut user_lists = self.joined_todo_lists();
+        user_lists.sort_by_key(|list| {
+            order
+                .iter()
+                .position(|id| *id == list.info.id())
+                .unwrap_or(order.len())
+        });
+        store_user_lists(self, user_lists);
+    }
+}
+
+fn update_joined_list(
+    session: &mut WritableSession,
+    id: Uuid,
+    update: impl FnOnce(&mut JoinedTodoList),
+) -> bool {
+    let mut user_lists = session.joined_todo_lists();
+    let Some(list) = user_lists.iter_mut().find(|list| list.info.id() == id) else {
+        return false;
+    };
+    update(list);
+    store_user_lists(session, user_lists);
+
+    true
+}
+
+fn store_user_lists(session: &mut WritableSession, user_lists: Vec<JoinedTodoList>) {
+    if session.insert("user_lists", user_lists).is_err() {
+        tracing::error!("Failed to update user session");
+    }
+}
+
+/// Username of the account the session is logged into, if any.
+pub fn session_account(session: &Session) -> Option<String> {
+    session.get::<String>("account")
+}
+
+/// Applies `update` to the lists joined by the session's user. Registered users' lists are
+/// kept with their account rather than in the session, so that every device logged into the
+/// account shares them.
+///
+/// Users taking part in lists can be mentioned in them, so they are indexed by handle here.
+pub async fn update_joined_lists<T>(
+    session: &mut WritableSession,
+    pool: Pool,
+    mut update: impl FnMut(&mut WritableSession) -> T,
+) -> anyhow::Result<T> {
+    if let Some(user) = session.get::<User>("user") {
+        user.index(pool.clone()).await?;
+    }
+    let registered = session_account(session).and(session.get::<User>("user"));
+    let Some(user) = registered else {
+        return Ok(update(session));
+    };
+    Account::update_joined_lists(*user.id(), pool, |lists| {
+        session.set_joined_todo_lists(std::mem::take(lists));
+        let result = update(session);
+        *lists = session.joined_todo_lists();
+        result
+    })
+    .await
+}
+
+/// Lists joined by the session's user.
+pub async fn joined_lists(session: &Session, pool: Pool) -> Vec<JoinedTodoList> {
+    let registered = session_account(session).and(session.get::<User>("user"));
+    match registered {
+        Some(user) => Account::joined_lists(*user.id(), pool)
+            .await
+            .expect("Failed to retrieve joined lists"),
+        None => session
+            .get::<Vec<JoinedTodoList>>("user_lists")
+            .unwrap_or_default(),
     }
 }
@@ -16,6 +16,33 @@ pub struct Settings {
 pub struct TodoHandlerSettings {
     #[serde_as(as = "DurationSeconds<u64>")]
     pub store_interval: Duration,
+    /// Commands per second a single connection can send, on average.
+    pub commands_per_second: u32,
+    /// Commands a single connection can send in a burst.
+    pub command_burst: u32,
+    /// Changes kept by each list for clients resuming a dropped connection.
+    pub resume_buffer_size: usize,
+    /// Time a user is still shown in a list after their connection dropped.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub presence_grace_period: Duration,
+    /// Interval between pings sent to connected clients.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub heartbeat_interval: Duration,
+    /// Connections that sent nothing, pongs included, for this long are closed.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub idle_timeout: Duration,
+    /// How long a claim on a task lasts unless renewed.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub lease_duration: Duration,
+    /// Messages kept in each list's chat.
+    pub chat_history_size: usize,
+    /// Words chat messages can't contain.
+    #[serde(default)]
+    pub chat_blocked_words: Vec<String>,
+    /// Failed logins allowed into an account per `login_attempt_window`.
+    pub login_attempts: u32,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub login_attempt_window: Duration,
 }
 
 #[derive(Debug, Deserialize)]
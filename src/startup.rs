@@ -1,4 +1,4 @@
-use std::net::TcpListener;
+use std::net::{SocketAddr, TcpListener};
 
 use axum::Router;
 use deadpool_redis::Pool;
@@ -9,8 +9,10 @@ use crate::{
     state::AppState,
 };
 
-pub type Server =
-    hyper::Server<hyper::server::conn::AddrIncoming, axum::routing::IntoMakeService<Router>>;
+pub type Server = hyper::Server<
+    hyper::server::conn::AddrIncoming,
+    axum::extract::connect_info::IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+>;
 
 #[allow(dead_code)]
 pub struct Application {
@@ -67,7 +69,7 @@ pub fn make_server(
         .with_state(state);
     let server = axum::Server::from_tcp(listener)
         .context("Cannot make server with the provided socket")?
-        .serve(router.into_make_service());
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>());
 
     Ok(server)
 }
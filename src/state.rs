@@ -1,28 +1,54 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
+use anyhow::Context;
 use deadpool_redis::Pool;
-use redis::JsonAsyncCommands;
-use tokio::sync::{oneshot, RwLock};
+use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::{
+    account::LoginLimiter,
+    notification::Notifier,
+    rate_limit::RateLimiter,
     settings::TodoHandlerSettings,
-    todo::{TodoCommandSender, TodoListHandle, TodoListInfo, TodoListWatcher},
+    todo::{
+        BlockedWords, Chat, ChatModerator, ListVisit, PresenceSender, PresenceUpdate,
+        TodoCommandSender, TodoList, TodoListConnection, TodoListHandle, TodoListInfo,
+    },
 };
 
 #[derive(Clone)]
 pub struct AppState {
     redis_pool: Pool,
+    notifier: Notifier,
     todo_lists: Arc<RwLock<HashMap<Uuid, TodoListHandle>>>,
-    store_interval: Duration,
+    commands_per_second: u32,
+    command_burst: u32,
+    resume_buffer_size: usize,
+    presence_grace_period: Duration,
+    heartbeat_interval: Duration,
+    idle_timeout: Duration,
+    lease_duration: Duration,
+    chat_history_size: usize,
+    chat_moderator: Arc<dyn ChatModerator>,
+    login_limiter: LoginLimiter,
 }
 
 impl AppState {
     pub fn new(redis_pool: Pool, config: &TodoHandlerSettings) -> Self {
         Self {
+            notifier: Notifier::new(redis_pool.clone()),
             redis_pool,
             todo_lists: Arc::new(RwLock::new(HashMap::default())),
-            store_interval: config.store_interval,
+            commands_per_second: config.commands_per_second,
+            command_burst: config.command_burst,
+            resume_buffer_size: config.resume_buffer_size,
+            presence_grace_period: config.presence_grace_period,
+            heartbeat_interval: config.heartbeat_interval,
+            idle_timeout: config.idle_timeout,
+            lease_duration: config.lease_duration,
+            chat_history_size: config.chat_history_size,
+            chat_moderator: Arc::new(BlockedWords::new(&config.chat_blocked_words)),
+            login_limiter: LoginLimiter::new(config.login_attempts, config.login_attempt_window),
         }
     }
 
@@ -30,56 +56,184 @@ impl AppState {
         self.redis_pool.clone()
     }
 
+    pub fn chat(&self, todo: Uuid) -> Chat {
+        Chat::new(
+            todo,
+            self.redis_pool(),
+            self.chat_history_size,
+            self.chat_moderator.clone(),
+        )
+    }
+
+    pub fn notifier(&self) -> &Notifier {
+        &self.notifier
+    }
+
+    /// Limits the rate at which a single connection can send commands.
+    pub fn command_rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.commands_per_second, self.command_burst)
+    }
+
+    pub const fn login_limiter(&self) -> &LoginLimiter {
+        &self.login_limiter
+    }
+
+    pub const fn presence_grace_period(&self) -> Duration {
+        self.presence_grace_period
+    }
+
+    pub const fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    pub const fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Connects `user_id` to a list, loading it if nobody is connected.
+    /// Fails if the list can't be loaded, e.g. because it doesn't exist.
     pub async fn join_todo_list(
         &self,
         todo: Uuid,
         user_id: Uuid,
-    ) -> (TodoListWatcher, TodoCommandSender, oneshot::Receiver<()>) {
+    ) -> anyhow::Result<TodoListConnection> {
         let mut todo_lists = self.todo_lists.write().await;
         let mut todo_list_handle = match todo_lists.remove(&todo) {
             Some(handle) => handle,
-            None => TodoListHandle::spawn(todo, self.redis_pool(), self.store_interval)
-                .await
-                .expect("Failed to spawn TodoListHandle"),
+            None => {
+                TodoListHandle::spawn(
+                    todo,
+                    self.redis_pool(),
+                    self.resume_buffer_size,
+                    self.lease_duration,
+                    self.notifier.clone(),
+                )
+                .await?
+            }
         };
         let connection_data = todo_list_handle.get_connection(user_id);
         todo_lists.insert(todo, todo_list_handle);
 
-        connection_data
+        Ok(connection_data)
     }
 
-    pub async fn leave_todo_list(&self, todo: Uuid, user_id: Uuid) {
+    /// Closes one of `user`'s connections to a list once the presence grace period has expired,
+    /// removing the user if they have no other connection by then.
+    pub fn schedule_leave(
+        &self,
+        todo: Uuid,
+        user: Uuid,
+        connection: Uuid,
+        presence_tx: PresenceSender,
+    ) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(state.presence_grace_period).await;
+            if state.leave_todo_list(todo, user, connection).await {
+                let _ = presence_tx.send(PresenceUpdate::Leave(user)).await;
+            }
+        });
+    }
+
+    /// Remembers that `user` has seen `todo` as of `revision`.
+    pub async fn record_visit(&self, user: Uuid, todo: Uuid, revision: u64) {
+        if let Err(e) = ListVisit::now(revision)
+            .record(user, todo, self.redis_pool())
+            .await
+        {
+            tracing::error!("Failed to record visit of list {todo}: {e:?}");
+        }
+    }
+
+    /// Sends commands to a todo list, loading it if nobody is connected.
+    /// The list stays loaded until [`Self::release_todo_list`] is called.
+    pub async fn todo_list_commands(&self, todo: Uuid) -> anyhow::Result<TodoCommandSender> {
+        let mut todo_lists = self.todo_lists.write().await;
+        if let Some(handle) = todo_lists.get_mut(&todo) {
+            return Ok(handle.hold());
+        }
+        let mut handle = TodoListHandle::spawn(
+            todo,
+            self.redis_pool(),
+            self.resume_buffer_size,
+            self.lease_duration,
+            self.notifier.clone(),
+        )
+        .await?;
+        let command_tx = handle.hold();
+        todo_lists.insert(todo, handle);
+
+        Ok(command_tx)
+    }
+
+    /// Releases a list held through [`Self::todo_list_commands`] and returns its current state.
+    /// The list is stored and unloaded if nobody else uses it.
+    pub async fn release_todo_list(&self, todo: Uuid) -> anyhow::Result<TodoList> {
+        let mut todo_lists = self.todo_lists.write().await;
+        let handle = todo_lists
+            .get_mut(&todo)
+            .context("Todo list has been unloaded while held")?;
+        handle.release();
+        let todo_list = handle.snapshot();
+        if handle.is_empty() {
+            todo_lists.remove(&todo);
+            // Stored right away so that the list can be loaded again before its task exits
+            todo_list.store(self.redis_pool()).await?;
+            tracing::debug!("TodoList {todo} has no user connected and has been docked");
+        }
+
+        Ok(todo_list)
+    }
+
+    /// Forgets a closed connection. Returns whether it was the user's last one,
+    /// in which case they have left the list.
+    pub async fn leave_todo_list(&self, todo: Uuid, user_id: Uuid, connection: Uuid) -> bool {
         let mut todo_lists = self.todo_lists.write().await;
         let mut empty = false;
+        let mut left = false;
         if let Some(handle) = todo_lists.get_mut(&todo) {
-            handle.disconnect_user(user_id);
-            tracing::debug!("User {user_id} has left TodoList {todo}");
+            left = handle.disconnect(user_id, connection);
+            if left {
+                tracing::debug!("User {user_id} has left TodoList {todo}");
+            }
             empty = handle.is_empty();
         }
         if empty {
-            todo_lists.remove(&todo);
+            if let Some(handle) = todo_lists.remove(&todo) {
+                // Stored right away so that the list can be loaded again before its task exits
+                if let Err(e) = handle.snapshot().store(self.redis_pool()).await {
+                    tracing::error!("Failed to store TodoList {todo}: {e:?}");
+                }
+            }
             tracing::debug!("TodoList {todo} has no user connected and has been docked");
         }
+        left
     }
 
-    pub async fn fill_todo_lists_info(&self, lists: &mut [TodoListInfo<'_>]) {
-        let mut redis = self
-            .redis_pool
-            .get()
-            .await
-            .expect("Failed to acquire redis connection");
+    /// Returns the current state of a todo list, reading it from its live handle
+    /// when loaded so that unsaved changes are included.
+    pub async fn get_todo_list(&self, todo: Uuid) -> anyhow::Result<TodoList> {
+        if let Some(handle) = self.todo_lists.read().await.get(&todo) {
+            return Ok(handle.snapshot());
+        }
+        TodoList::from_redis(todo, self.redis_pool()).await
+    }
+
+    pub async fn fill_todo_lists_info<'l, 't: 'l>(
+        &self,
+        lists: impl IntoIterator<Item = &'l mut TodoListInfo<'t>>,
+    ) {
         let todo_lists = self.todo_lists.read().await;
-        for list in lists.iter_mut() {
+        for list in lists {
             if let Some(todo) = todo_lists.get(&list.id()) {
                 *list = todo.peek();
             } else {
-                let list_name = redis
-                    .json_get::<_, _, String>(list.id().to_string(), "name")
-                    .await
-                    .unwrap()
-                    .trim_matches('"')
-                    .to_owned();
-                list.set_name(list_name);
+                match TodoListInfo::from_redis(list.id(), self.redis_pool()).await {
+                    Ok(info) => *list = info,
+                    Err(e) => {
+                        tracing::error!("Failed to retrieve info of list {}: {e:?}", list.id())
+                    }
+                }
             }
         }
     }
@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use deadpool_redis::{Connection, Pool};
+use redis::AsyncCommands;
+use redis_macros::{FromRedisValue, ToRedisArgs};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::user::User;
+
+use super::CommandError;
+
+/// Longest chat message accepted, in characters.
+const MAX_MESSAGE_LEN: usize = 2000;
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, FromRedisValue, ToRedisArgs,
+)]
+pub struct ChatMessage {
+    id: Uuid,
+    author: User,
+    text: String,
+    sent_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edited_at: Option<DateTime<Utc>>,
+}
+
+impl ChatMessage {
+    pub const fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub const fn author(&self) -> &User {
+        &self.author
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub const fn sent_at(&self) -> DateTime<Utc> {
+        self.sent_at
+    }
+
+    pub const fn edited_at(&self) -> Option<DateTime<Utc>> {
+        self.edited_at
+    }
+}
+
+/// Chat messages sent by clients over a list's WebSocket.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ChatCommand {
+    SendChat {
+        text: String,
+    },
+    /// Only the author of a message can edit it.
+    EditChat {
+        message: Uuid,
+        text: String,
+    },
+    /// Only the author of a message can delete it.
+    DeleteChat {
+        message: Uuid,
+    },
+}
+
+/// A chat command, optionally tagged with an id echoed back if it's rejected.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ChatRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub command: ChatCommand,
+}
+
+/// Changes to a list's chat, pushed to everyone connected to the list.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChatEvent {
+    Sent { message: ChatMessage },
+    Edited { message: ChatMessage },
+    Deleted { message: Uuid },
+}
+
+/// A slice of a list's chat history, oldest message first.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ChatPage {
+    pub messages: Vec<ChatMessage>,
+    /// Whether older messages are available.
+    pub has_more: bool,
+}
+
+/// Hook deciding whether a chat message may be posted, and possibly rewriting it.
+pub trait ChatModerator: Send + Sync {
+    /// Returns the text to post, or why the message is refused.
+    fn moderate(&self, list: Uuid, author: &User, text: String) -> Result<String, String>;
+}
+
+/// Refuses messages containing any of the given words, ignoring case.
+pub struct BlockedWords(Vec<String>);
+
+impl BlockedWords {
+    pub fn new(words: &[String]) -> Self {
+        Self(words.iter().map(|word| word.to_lowercase()).collect())
+    }
+}
+
+impl ChatModerator for BlockedWords {
+    fn moderate(&self, _list: Uuid, _author: &User, text: String) -> Result<String, String> {
+        let lowercase = text.to_lowercase();
+        let blocked = lowercase
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| self.0.iter().any(|blocked| blocked == word));
+        if blocked {
+            return Err("Message contains a blocked word".to_owned());
+        }
+        Ok(text)
+    }
+}
+
+/// A list's chat. Its history is stored apart from the list, and bounded.
+pub struct Chat {
+    list: Uuid,
+    pool: Pool,
+    capacity: usize,
+    moderator: Arc<dyn ChatModerator>,
+}
+
+impl Chat {
+    pub fn new(list: Uuid, pool: Pool, capacity: usize, moderator: Arc<dyn ChatModerator>) -> Self {
+        Self {
+            list,
+            pool,
+            capacity,
+            moderator,
+        }
+    }
+
+    /// Applies a chat command from `author`, returning the event to broadcast
+    /// or why the command has been refused.
+    pub async fn apply(
+        &self,
+        command: ChatCommand,
+        author: &User,
+    ) -> anyhow::Result<Result<ChatEvent, CommandError>> {
+        let event = match command {
+            ChatCommand::SendChat { text } => {
+                let text = match self.moderate(author, text) {
+                    Ok(text) => text,
+                    Err(e) => return Ok(Err(e)),
+                };
+                let message = ChatMessage {
+                    id: Uuid::new_v4(),
+                    author: author.clone(),
+                    text,
+                    sent_at: Utc::now(),
+                    edited_at: None,
+                };
+                self.push(&message).await?;
+                ChatEvent::Sent { message }
+            }
+            ChatCommand::EditChat { message, text } => {
+                match self.edit(message, text, author).await? {
+                    Ok(message) => ChatEvent::Edited { message },
+                    Err(e) => return Ok(Err(e)),
+                }
+            }
+            ChatCommand::DeleteChat { message } => {
+                let mut redis = self.pool.get().await?;
+                if let Err(e) = self.own_message(&mut redis, message, author).await? {
+                    return Ok(Err(e));
+                }
+                redis::pipe()
+                    .atomic()
+                    .hdel(messages_key(self.list), message.to_string())
+                    .zrem(order_key(self.list), message.to_string())
+                    .query_async::<_, ()>(&mut redis)
+                    .await?;
+                ChatEvent::Deleted { message }
+            }
+        };
+
+        Ok(Ok(event))
+    }
+
+    /// Returns up to `limit` messages sent before `before`, or the latest ones.
+    pub async fn page(
+        &self,
+        before: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> anyhow::Result<ChatPage> {
+        let mut redis = self.pool.get().await?;
+        let max = before.map_or("+inf".to_owned(), |before| format!("({}", score(before)));
+        // One more than asked for, to know whether there are older messages
+        let mut ids: Vec<String> = redis
+            .zrevrangebyscore_limit(order_key(self.list), max, "-inf", 0, limit as isize + 1)
+            .await?;
+        let has_more = ids.len() > limit;
+        ids.truncate(limit);
+        ids.reverse();
+        let messages: Vec<Option<ChatMessage>> = if ids.is_empty() {
+            vec![]
+        } else {
+            redis::cmd("HMGET")
+                .arg(messages_key(self.list))
+                .arg(ids)
+                .query_async(&mut redis)
+                .await?
+        };
+
+        Ok(ChatPage {
+            messages: messages.into_iter().flatten().collect(),
+            has_more,
+        })
+    }
+
+    fn moderate(&self, author: &User, text: String) -> Result<String, CommandError> {
+        let text = text.trim().to_owned();
+        if text.is_empty() || text.chars().count() > MAX_MESSAGE_LEN {
+            return Err(CommandError::Malformed {
+                message: format!("Chat messages must have 1 to {MAX_MESSAGE_LEN} characters"),
+            });
+        }
+        self.moderator
+            .moderate(self.list, author, text)
+            .map_err(|explanation| CommandError::Moderated { explanation })
+    }
+
+    /// Edits a message, unless it is deleted meanwhile.
+    async fn edit(
+        &self,
+        message: Uuid,
+        text: String,
+        author: &User,
+    ) -> anyhow::Result<Result<ChatMessage, CommandError>> {
+        let mut redis = self.pool.get().await?;
+        loop {
+            redis::cmd("WATCH")
+                .arg(messages_key(self.list))
+                .query_async::<_, ()>(&mut redis)
+                .await?;
+            let edited = self
+                .own_message(&mut redis, message, author)
+                .await?
+                .and_then(|mut message| {
+                    message.text = self.moderate(author, text.clone())?;
+                    message.edited_at = Some(Utc::now());
+                    Ok(message)
+                });
+            let edited = match edited {
+                Ok(edited) => edited,
+                Err(e) => {
+                    redis::cmd("UNWATCH")
+                        .query_async::<_, ()>(&mut redis)
+                        .await?;
+                    return Ok(Err(e));
+                }
+            };
+            // Aborted if the chat has changed since being watched
+            let stored: Option<()> = redis::pipe()
+                .atomic()
+                .hset(messages_key(self.list), message.to_string(), &edited)
+                .ignore()
+                .query_async(&mut redis)
+                .await?;
+            if stored.is_some() {
+                return Ok(Ok(edited));
+            }
+        }
+    }
+
+    async fn own_message(
+        &self,
+        redis: &mut Connection,
+        message: Uuid,
+        author: &User,
+    ) -> anyhow::Result<Result<ChatMessage, CommandError>> {
+        let stored: Option<ChatMessage> = redis
+            .hget(messages_key(self.list), message.to_string())
+            .await?;
+        Ok(match stored {
+            None => Err(CommandError::UnknownMessage { message }),
+            Some(stored) if stored.author.id() != author.id() => {
+                Err(CommandError::NotAuthor { message })
+            }
+            Some(stored) => Ok(stored),
+        })
+    }
+
+    /// Adds a message, dropping the oldest ones past the chat's capacity in the same
+    /// transaction.
+    async fn push(&self, message: &ChatMessage) -> anyhow::Result<()> {
+        let mut redis = self.pool.get().await?;
+        let id = message.id.to_string();
+        loop {
+            redis::cmd("WATCH")
+                .arg(order_key(self.list))
+                .query_async::<_, ()>(&mut redis)
+                .await?;
+            let len: usize = redis.zcard(order_key(self.list)).await?;
+            let excess = (len + 1).saturating_sub(self.capacity);
+            let mut oldest: Vec<(String, f64)> = if excess == 0 {
+                vec![]
+            } else {
+                redis
+                    .zrange_withscores(order_key(self.list), 0, excess as isize - 1)
+                    .await?
+            };
+            // The message itself is dropped if it is older than those kept
+            oldest.push((id.clone(), score(message.sent_at) as f64));
+            oldest.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+            let dropped: Vec<String> = oldest.into_iter().take(excess).map(|(id, _)| id).collect();
+
+            let mut pipe = redis::pipe();
+            pipe.atomic()
+                .hset(messages_key(self.list), &id, message)
+                .ignore()
+                .zadd(order_key(self.list), &id, score(message.sent_at))
+                .ignore();
+            if !dropped.is_empty() {
+                pipe.zrem(order_key(self.list), &dropped)
+                    .ignore()
+                    .hdel(messages_key(self.list), &dropped)
+                    .ignore();
+            }
+            // Aborted if the chat has changed since being watched
+            let stored: Option<()> = pipe.query_async(&mut redis).await?;
+            if stored.is_some() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn messages_key(list: Uuid) -> String {
+    format!("chat_messages:{list}")
+}
+
+/// Messages are ordered by when they were sent, to the microsecond.
+fn score(sent_at: DateTime<Utc>) -> i64 {
+    sent_at.timestamp_micros()
+}
+
+fn order_key(list: Uuid) -> String {
+    format!("chat:{list}")
+}
@@ -1,28 +1,232 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
 use crate::user::User;
 
-use super::list::{TodoList, TodoTask};
+use super::{
+    list::{TodoList, TodoTask},
+    merge::OfflineOrigin,
+    text::{TextEdit, TextField},
+};
+
+pub type CommandResult = Result<(), CommandError>;
+/// Outcome of a command as reported to its issuer.
+pub type CommandReply = Result<AppliedCommand, CommandError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedCommand {
+    /// Revision of the list once the command has been applied.
+    pub revision: u64,
+    /// Whether the command has been adapted to changes it wasn't based on.
+    pub transformed: bool,
+    /// Tasks whose blockers have all been completed by the command.
+    pub unblocked: Vec<Uuid>,
+    /// Task created by the command.
+    pub created: Option<Uuid>,
+}
 
 pub trait Applicable {
-    fn apply(self, todo: &mut TodoList, issuer: User);
+    fn apply(self, todo: &mut TodoList, issuer: User) -> CommandResult;
 }
 
 #[derive(Debug)]
 pub struct TodoCommand {
     pub issuer: User,
     pub command: Command,
+    pub reply: Option<oneshot::Sender<CommandReply>>,
+    /// Revision of the list the issuer based this command on.
+    pub expected_revision: Option<u64>,
+    /// Set for commands made offline, which are merged rather than checked for conflicts.
+    pub offline: Option<OfflineOrigin>,
+    /// Connection the command has been sent through, if any.
+    pub connection: Option<Uuid>,
+}
+
+impl TodoCommand {
+    /// Rejects the command if what it edits has changed since `revision`.
+    pub fn expecting_revision(mut self, revision: Option<u64>) -> Self {
+        self.expected_revision = revision;
+        self
+    }
+
+    /// Merges the command according to [`Command::rebase`].
+    pub fn made_offline(mut self, origin: OfflineOrigin) -> Self {
+        self.offline = Some(origin);
+        self
+    }
+
+    /// Ties the leases claimed by this command to `connection`.
+    pub fn through_connection(mut self, connection: Uuid) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    /// Asks the todo list's task to report whether this command has been applied.
+    pub fn with_reply(mut self) -> (Self, oneshot::Receiver<CommandReply>) {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.reply = Some(reply_tx);
+        (self, reply_rx)
+    }
+}
+
+/// Reason why a command has been rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum CommandError {
+    Malformed {
+        message: String,
+    },
+    RateLimited,
+    UnknownTask {
+        task: Uuid,
+    },
+    UnknownBlocker {
+        task: Uuid,
+        blocker: Uuid,
+    },
+    DependencyCycle {
+        task: Uuid,
+        blocker: Uuid,
+    },
+    OpenBlockers {
+        task: Uuid,
+        blockers: Vec<Uuid>,
+    },
+    InvalidColor {
+        color: String,
+    },
+    InvalidIcon {
+        icon: String,
+    },
+    Conflict {
+        expected_revision: u64,
+        revision: u64,
+    },
+    /// An offline command would overwrite a more recent change.
+    Superseded {
+        changed_at: DateTime<Utc>,
+    },
+    /// Another user holds a lease on the task.
+    TaskClaimed {
+        task: Uuid,
+        holder: User,
+        expires_at: DateTime<Utc>,
+    },
+    /// Leases are only given to users connected to the list.
+    NotConnected,
+    UnknownMessage {
+        message: Uuid,
+    },
+    /// Chat messages can only be changed by their author.
+    NotAuthor {
+        message: Uuid,
+    },
+    /// The chat's moderation refused the message.
+    Moderated {
+        explanation: String,
+    },
+    /// The command couldn't be carried out, e.g. because storage failed. It may be retried.
+    Unavailable,
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Malformed { message } => write!(f, "Malformed command: {message}"),
+            CommandError::RateLimited => f.write_str("Too many commands, slow down"),
+            CommandError::UnknownTask { task } => write!(f, "No task {task} in this list"),
+            CommandError::UnknownBlocker { task, blocker } => {
+                write!(
+                    f,
+                    "Task {task} cannot be blocked by {blocker}: no such task in this list"
+                )
+            }
+            CommandError::DependencyCycle { task, blocker } => {
+                write!(
+                    f,
+                    "Task {task} cannot be blocked by {blocker}: it would create a cycle"
+                )
+            }
+            CommandError::OpenBlockers { task, blockers } => {
+                write!(
+                    f,
+                    "Task {task} is blocked by {} open task(s)",
+                    blockers.len()
+                )
+            }
+            CommandError::InvalidColor { color } => {
+                write!(f, "{color} is not a color in the #rrggbb format")
+            }
+            CommandError::InvalidIcon { icon } => write!(f, "{icon} is not a valid icon"),
+            CommandError::Conflict {
+                expected_revision,
+                revision,
+            } => write!(
+                f,
+                "Command based on revision {expected_revision} but its target changed at revision {revision}"
+            ),
+            CommandError::Superseded { changed_at } => {
+                write!(f, "Command superseded by a change made at {changed_at}")
+            }
+            CommandError::TaskClaimed {
+                task,
+                holder,
+                expires_at,
+            } => write!(
+                f,
+                "Task {task} is being edited by {holder} until {expires_at}"
+            ),
+            CommandError::NotConnected => {
+                f.write_str("Connect to the list to claim one of its tasks")
+            }
+            CommandError::UnknownMessage { message } => {
+                write!(f, "No message {message} in this chat")
+            }
+            CommandError::NotAuthor { message } => {
+                write!(f, "Message {message} has been sent by someone else")
+            }
+            CommandError::Moderated { explanation } => {
+                write!(f, "Message refused: {explanation}")
+            }
+            CommandError::Unavailable => {
+                f.write_str("Command couldn't be carried out, try again later")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// A command sent by a client, optionally tagged with an id
+/// that will be echoed back in the reply.
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct CommandRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_revision: Option<u64>,
+    #[serde(flatten)]
+    pub command: Command,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum Command {
     TaskCommand(TaskCommandMeta),
     CreateTask,
-    UserJoin(User),
-    UserLeave(User),
     SetListName(String),
+    SetListDescription(String),
+    SetListIcon(Option<String>),
+    SetListColor(Option<String>),
+    /// Takes or renews a lease on a task, so that other users can't edit it for a while.
+    ClaimTask(Uuid),
+    ReleaseTask(Uuid),
 }
 
 impl Command {
@@ -30,58 +234,185 @@ impl Command {
         TodoCommand {
             issuer,
             command: self,
+            reply: None,
+            expected_revision: None,
+            offline: None,
+            connection: None,
+        }
+    }
+
+    /// The task whose content this command edits, if any.
+    pub fn edited_task(&self) -> Option<Uuid> {
+        match self {
+            Command::TaskCommand(TaskCommandMeta {
+                command: TaskCommand::StartTimer | TaskCommand::StopTimer,
+                ..
+            }) => None,
+            Command::TaskCommand(task_command) => Some(task_command.task),
+            _ => None,
         }
     }
+
+    /// Checks that nothing this command edits has changed after `expected_revision`.
+    ///
+    /// Task commands only conflict with edits to the same task, so concurrent edits to
+    /// different tasks are merged. Edits to the list itself conflict with any change.
+    pub fn check_revision(&self, todo: &TodoList, expected_revision: u64) -> CommandResult {
+        let revision = match self {
+            Command::CreateTask | Command::ClaimTask(_) | Command::ReleaseTask(_) => return Ok(()),
+            Command::TaskCommand(_) => match self.edited_task().and_then(|task| todo.task(task)) {
+                Some(task) => task.revision(),
+                None => return Ok(()),
+            },
+            _ => todo.revision(),
+        };
+        if revision > expected_revision {
+            return Err(CommandError::Conflict {
+                expected_revision,
+                revision,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl Applicable for Command {
-    fn apply(self, todo: &mut TodoList, issuer: User) {
+    fn apply(self, todo: &mut TodoList, issuer: User) -> CommandResult {
         match self {
-            Command::TaskCommand(task_command) => task_command.apply(todo, issuer),
+            Command::TaskCommand(task_command) => return task_command.apply(todo, issuer),
             Command::CreateTask => todo.add_task(TodoTask::new(issuer)),
-            Command::UserJoin(user) => todo.add_user(user),
-            Command::UserLeave(user) => todo.remove_user(*user.id()),
             Command::SetListName(name) => todo.rename(name),
+            Command::SetListDescription(description) => todo.set_description(description),
+            Command::SetListIcon(icon) => {
+                if let Some(icon) = icon.as_ref().filter(|icon| !is_valid_icon(icon)) {
+                    return Err(CommandError::InvalidIcon { icon: icon.clone() });
+                }
+                todo.set_icon(icon)
+            }
+            Command::SetListColor(color) => {
+                if let Some(color) = color.as_ref().filter(|color| !is_valid_color(color)) {
+                    return Err(CommandError::InvalidColor {
+                        color: color.clone(),
+                    });
+                }
+                todo.set_color(color)
+            }
+            // Leases are kept by the list's task, outside of the list
+            Command::ClaimTask(_) | Command::ReleaseTask(_) => (),
         }
+
+        Ok(())
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Icons are either an emoji or the name of an icon known by clients.
+fn is_valid_icon(icon: &str) -> bool {
+    const MAX_ICON_LEN: usize = 32;
+    !icon.trim().is_empty() && icon.chars().count() <= MAX_ICON_LEN
+}
+
+fn is_valid_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 pub struct TaskCommandMeta {
     pub task: Uuid,
     #[serde(flatten)]
     pub command: TaskCommand,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[serde_as]
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(tag = "action", content = "data", rename_all = "snake_case")]
 pub enum TaskCommand {
     SetDone(bool),
     Rename(String),
+    SetDescription(String),
+    /// Collaborative edit of the task's name or description.
+    EditText(TextEdit),
     SetAssignee(User),
+    SetEstimate(
+        #[serde_as(as = "Option<DurationSeconds<u64>>")]
+        #[schemars(with = "Option<u64>")]
+        Option<Duration>,
+    ),
+    StartTimer,
+    StopTimer,
+    AddBlocker(Uuid),
+    RemoveBlocker(Uuid),
 }
 
-impl Applicable for TaskCommandMeta {
-    fn apply(self, todo: &mut TodoList, issuer: User) {
-        if let Some(task) = todo.task_mut(self.task) {
-            match self.command {
-                TaskCommand::SetDone(is_done) => {
-                    task.set_done(is_done);
-                    task.assign_to(issuer)
+impl TaskCommandMeta {
+    fn validate(&self, todo: &TodoList) -> CommandResult {
+        let task = self.task;
+        match self.command {
+            TaskCommand::SetDone(true) => {
+                let blockers = todo.open_blockers(task);
+                if !blockers.is_empty() {
+                    return Err(CommandError::OpenBlockers { task, blockers });
                 }
-                TaskCommand::Rename(name) => {
-                    task.rename(name);
+            }
+            TaskCommand::AddBlocker(blocker) => {
+                if todo.task(blocker).is_none() {
+                    return Err(CommandError::UnknownBlocker { task, blocker });
                 }
-                TaskCommand::SetAssignee(assignee) => task.assign_to(assignee),
+                if blocker == task || todo.depends_on(blocker, task) {
+                    return Err(CommandError::DependencyCycle { task, blocker });
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl Applicable for TaskCommandMeta {
+    fn apply(self, todo: &mut TodoList, issuer: User) -> CommandResult {
+        if todo.task(self.task).is_none() {
+            return Err(CommandError::UnknownTask { task: self.task });
+        }
+        self.validate(todo)?;
+
+        let revision = todo.revision();
+        let task = todo
+            .task_mut(self.task)
+            .expect("Task existence already checked");
+        match self.command {
+            TaskCommand::SetDone(is_done) => {
+                task.set_done(is_done);
+                task.assign_to(issuer);
+            }
+            TaskCommand::Rename(name) => task.replace_text(TextField::Name, name, revision),
+            TaskCommand::SetDescription(description) => {
+                task.replace_text(TextField::Description, description, revision)
             }
+            TaskCommand::EditText(edit) => task.edit_text(edit, revision)?,
+            TaskCommand::SetAssignee(assignee) => task.assign_to(assignee),
+            TaskCommand::SetEstimate(estimate) => task.set_estimate(estimate),
+            // Timers are tracked outside of the list by the list's task
+            TaskCommand::StartTimer | TaskCommand::StopTimer => (),
+            TaskCommand::AddBlocker(blocker) => task.add_blocker(blocker),
+            TaskCommand::RemoveBlocker(blocker) => task.remove_blocker(blocker),
         }
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::{Command, TaskCommand, TaskCommandMeta};
+    use crate::{todo::TodoList, user::User};
+
+    use super::{
+        is_valid_color, Applicable, Command, CommandError, CommandRequest, TaskCommand,
+        TaskCommandMeta,
+    };
 
     #[test]
     fn create_task_command_deserialization_works() {
@@ -115,4 +446,84 @@ mod tests {
             })
         )));
     }
+
+    #[test]
+    fn set_estimate_command_deserialization_works() {
+        let command_json = r#"
+{
+    "type": "task_command",
+    "data": {
+        "task": "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8",
+        "action": "set_estimate",
+        "data": 5400
+    }
+}
+        "#;
+        let command = serde_json::from_str::<Command>(command_json);
+        assert!(command.is_ok_and(|cmd| matches!(
+            cmd,
+            Command::TaskCommand(TaskCommandMeta {
+                command: TaskCommand::SetEstimate(Some(estimate)),
+                ..
+            }) if estimate.as_secs() == 5400
+        )));
+    }
+
+    #[test]
+    fn color_validation_works() {
+        assert!(is_valid_color("#1a2B3c"));
+        assert!(!is_valid_color("1a2b3c"));
+        assert!(!is_valid_color("#1a2b3"));
+        assert!(!is_valid_color("#1a2b3g"));
+    }
+
+    #[test]
+    fn command_request_deserialization_works() {
+        let request_json = r#"
+{
+    "id": "req-1",
+    "type": "set_list_name",
+    "data": "groceries"
+}
+        "#;
+        let request = serde_json::from_str::<CommandRequest>(request_json);
+        assert!(request.is_ok_and(|req| req.id.as_deref() == Some("req-1")
+            && matches!(req.command, Command::SetListName(name) if name == "groceries")));
+
+        let command_json = r#"{ "type": "create_task" }"#;
+        let request = serde_json::from_str::<CommandRequest>(command_json);
+        assert!(request.is_ok_and(|req| req.id.is_none()));
+    }
+
+    #[tokio::test]
+    async fn only_edits_to_the_same_task_conflict() {
+        let user = User::new().await;
+        let mut todo = TodoList::default();
+        Command::CreateTask.apply(&mut todo, user.clone()).unwrap();
+        Command::CreateTask.apply(&mut todo, user.clone()).unwrap();
+        let (first, second) = (todo.tasks()[0].id(), todo.tasks()[1].id());
+        let rename = |task| {
+            Command::TaskCommand(TaskCommandMeta {
+                task,
+                command: TaskCommand::Rename("renamed".to_owned()),
+            })
+        };
+
+        let base = todo.revision();
+        let revision = todo.bump_revision();
+        todo.task_mut(first).unwrap().touch(revision);
+
+        assert_eq!(
+            rename(first).check_revision(&todo, base),
+            Err(CommandError::Conflict {
+                expected_revision: base,
+                revision
+            })
+        );
+        assert!(rename(second).check_revision(&todo, base).is_ok());
+        assert!(rename(first).check_revision(&todo, revision).is_ok());
+        assert!(Command::SetListName("renamed".to_owned())
+            .check_revision(&todo, base)
+            .is_err());
+    }
 }
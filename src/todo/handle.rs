@@ -1,29 +1,73 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use super::{
-    list::TodoList, task::todo_list_task, TodoCommandSender, TodoListInfo, TodoListWatcher,
+    list::TodoList,
+    task::{todo_list_task, TodoListContext},
+    ChatReceiver, ChatSender, PresenceSender, PresenceUpdate, PresenceWatcher, TodoCommandSender,
+    TodoListChangeReceiver, TodoListChangeSender, TodoListHistory, TodoListInfo, TodoListWatcher,
 };
 
 use anyhow::Context;
 use deadpool_redis::Pool;
-use tokio::{sync::oneshot, task::JoinHandle};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+use crate::{notification::Notifier, user::User};
+
+/// A user's connection to a live todo list. Users may have several at once, e.g. one per tab.
+#[derive(Debug)]
+pub struct TodoListConnection {
+    pub id: Uuid,
+    pub todo: TodoListWatcher,
+    pub changes: TodoListChangeReceiver,
+    pub command_tx: TodoCommandSender,
+    pub history: TodoListHistory,
+    pub presence: PresenceWatcher,
+    pub presence_tx: PresenceSender,
+    pub chat: ChatReceiver,
+    pub chat_tx: ChatSender,
+}
+
+impl TodoListConnection {
+    /// Adds `user` to the list's presence. Users already there, through another connection
+    /// or because their previous one dropped within the presence grace period, have their
+    /// state reset.
+    pub async fn announce(&self, user: &User) -> anyhow::Result<()> {
+        self.presence_tx
+            .send(PresenceUpdate::Join(user.clone()))
+            .await
+            .context("Todo list task stopped")
+    }
+}
+
 #[derive(Debug)]
 pub struct TodoListHandle {
     command_tx: TodoCommandSender,
     todo_watcher: TodoListWatcher,
+    change_tx: TodoListChangeSender,
+    history: TodoListHistory,
+    presence_tx: PresenceSender,
+    presence_watcher: PresenceWatcher,
+    chat_tx: ChatSender,
     _task_handle: JoinHandle<()>,
-    connected_users: HashMap<Uuid, oneshot::Sender<()>>,
+    /// Open connections of each connected user.
+    connected_users: HashMap<Uuid, HashSet<Uuid>>,
+    /// Requests sending commands to the list without being connected to it.
+    holders: usize,
 }
 
 impl TodoListHandle {
     pub async fn spawn(
         list_id: Uuid,
         pool: Pool,
-        store_interval: Duration,
+        history_size: usize,
+        lease_duration: Duration,
+        notifier: Notifier,
     ) -> anyhow::Result<Self> {
-        use tokio::sync::{mpsc, watch};
+        use tokio::sync::{broadcast, mpsc, watch};
 
         let todo_list = TodoList::from_redis(list_id, pool.clone())
             .await
@@ -31,46 +75,93 @@ impl TodoListHandle {
 
         let todo_id = todo_list.id();
         let (watch_tx, watch_rx) = watch::channel(todo_list);
+        let (change_tx, _) = broadcast::channel(64);
         let (command_tx, command_rx) = mpsc::channel(16);
+        let (presence_tx, presence_rx) = mpsc::channel(16);
+        let (presence_updater, presence_watcher) = watch::channel(Vec::new());
+        let (chat_tx, _) = broadcast::channel(64);
+        let history = TodoListHistory::new(history_size);
         let task_handle = tokio::spawn(todo_list_task(
             todo_id,
             watch_tx,
+            change_tx.clone(),
             command_rx,
-            pool,
-            store_interval,
+            presence_updater,
+            presence_rx,
+            TodoListContext {
+                pool,
+                notifier,
+                history: history.clone(),
+                lease_duration,
+            },
         ));
 
         Ok(Self {
             command_tx,
             todo_watcher: watch_rx,
+            change_tx,
+            history,
+            presence_tx,
+            presence_watcher,
+            chat_tx,
             _task_handle: task_handle,
             connected_users: HashMap::default(),
+            holders: 0,
         })
     }
 
-    pub fn get_connection(
-        &mut self,
-        user: Uuid,
-    ) -> (TodoListWatcher, TodoCommandSender, oneshot::Receiver<()>) {
-        let (abort_tx, abort_rx) = oneshot::channel();
+    pub fn get_connection(&mut self, user: Uuid) -> TodoListConnection {
+        let id = Uuid::new_v4();
+        self.connected_users.entry(user).or_default().insert(id);
 
-        if let Some(prev_session_abort_tx) = self.connected_users.insert(user, abort_tx) {
-            let _ = prev_session_abort_tx.send(());
+        TodoListConnection {
+            id,
+            todo: self.todo_watcher.clone(),
+            changes: self.change_tx.subscribe(),
+            command_tx: self.command_tx.clone(),
+            history: self.history.clone(),
+            presence: self.presence_watcher.clone(),
+            presence_tx: self.presence_tx.clone(),
+            chat: self.chat_tx.subscribe(),
+            chat_tx: self.chat_tx.clone(),
         }
+    }
+
+    /// Sends commands to the list without connecting to it. The list is kept loaded until
+    /// [`Self::release`] is called.
+    pub fn hold(&mut self) -> TodoCommandSender {
+        self.holders += 1;
+        self.command_tx.clone()
+    }
 
-        (self.todo_watcher.clone(), self.command_tx.clone(), abort_rx)
+    pub fn release(&mut self) {
+        self.holders = self.holders.saturating_sub(1);
     }
 
     pub fn peek(&self) -> TodoListInfo<'static> {
-        let list = self.todo_watcher.borrow();
-        TodoListInfo::new_owned(list.id(), list.name().to_owned())
+        self.todo_watcher.borrow().as_info().into_owned()
     }
 
-    pub fn disconnect_user(&mut self, user: Uuid) {
-        self.connected_users.remove(&user);
+    pub fn snapshot(&self) -> TodoList {
+        self.todo_watcher.borrow().clone()
+    }
+
+    /// Forgets one of `user`'s connections, and the user too if it was their last one.
+    /// Returns whether the user has been disconnected.
+    pub fn disconnect(&mut self, user: Uuid, connection: Uuid) -> bool {
+        let Some(connections) = self.connected_users.get_mut(&user) else {
+            return false;
+        };
+        connections.remove(&connection);
+        let disconnected = connections.is_empty();
+        if disconnected {
+            self.connected_users.remove(&user);
+        }
+        disconnected
     }
 
+    /// Whether nobody is connected to the list nor holding it.
     pub fn is_empty(&self) -> bool {
-        self.connected_users.is_empty()
+        self.connected_users.is_empty() && self.holders == 0
     }
 }
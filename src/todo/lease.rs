@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::user::User;
+
+use super::{CommandError, CommandResult, TodoListPresence};
+
+/// Short-lived right to edit a task, held by a connected user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TaskLease {
+    pub task: Uuid,
+    pub expires_at: DateTime<Utc>,
+    /// Connection the task has been claimed through, releasing the lease when it closes.
+    #[serde(skip)]
+    pub connection: Uuid,
+}
+
+impl TaskLease {
+    fn is_held(&self, task: Uuid, now: DateTime<Utc>) -> bool {
+        self.task == task && self.expires_at > now
+    }
+}
+
+/// Rejects edits to `task` by anyone but the user holding a lease on it.
+pub fn check(presence: &TodoListPresence, task: Uuid, user: &User) -> CommandResult {
+    let now = Utc::now();
+    let holder = presence.iter().find(|p| {
+        p.user.id() != user.id() && p.lease.as_ref().is_some_and(|l| l.is_held(task, now))
+    });
+    match holder {
+        Some(holder) => Err(CommandError::TaskClaimed {
+            task,
+            holder: holder.user.clone(),
+            expires_at: holder.lease.as_ref().map_or(now, |lease| lease.expires_at),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Gives `user` a lease on `task` for `duration`, replacing any lease they held.
+/// Claiming a task again renews the lease.
+pub fn claim(
+    presence: &mut TodoListPresence,
+    task: Uuid,
+    user: &User,
+    connection: Option<Uuid>,
+    duration: Duration,
+) -> CommandResult {
+    check(presence, task, user)?;
+    let (Some(connection), Some(claimer)) =
+        (connection, presence.iter_mut().find(|p| p.user == *user))
+    else {
+        return Err(CommandError::NotConnected);
+    };
+    claimer.lease = Some(TaskLease {
+        task,
+        expires_at: Utc::now()
+            + chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::zero()),
+        connection,
+    });
+
+    Ok(())
+}
+
+/// Returns whether `user` held a lease on `task`.
+pub fn release(presence: &mut TodoListPresence, task: Uuid, user: &User) -> bool {
+    presence
+        .iter_mut()
+        .find(|p| p.user == *user)
+        .and_then(|p| p.lease.take_if(|lease| lease.task == task))
+        .is_some()
+}
+
+/// Releases leases claimed through a closed connection. Returns whether there were any.
+pub fn release_connection(presence: &mut TodoListPresence, connection: Uuid) -> bool {
+    presence
+        .iter_mut()
+        .filter_map(|p| p.lease.take_if(|lease| lease.connection == connection))
+        .count()
+        > 0
+}
+
+/// Releases expired leases. Returns whether there were any.
+pub fn expire(presence: &mut TodoListPresence) -> bool {
+    let now = Utc::now();
+    presence
+        .iter_mut()
+        .filter_map(|p| p.lease.take_if(|lease| lease.expires_at <= now))
+        .count()
+        > 0
+}
+
+/// Time until the next lease expires, if any is held.
+pub fn next_expiry(presence: &TodoListPresence) -> Option<Duration> {
+    presence
+        .iter()
+        .filter_map(|p| p.lease.as_ref())
+        .map(|lease| lease.expires_at)
+        .min()
+        .map(|expires_at| (expires_at - Utc::now()).to_std().unwrap_or_default())
+}
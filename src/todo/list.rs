@@ -1,19 +1,43 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, time::Duration};
 
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 use deadpool_redis::Pool;
 use redis::JsonAsyncCommands;
 use redis_macros::{FromRedisValue, ToRedisArgs};
-use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_with::{serde_as, DurationSeconds};
 use uuid::Uuid;
 
 use crate::user::User;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+use super::{
+    text::{TextEdit, TextField, TextLog},
+    CommandResult,
+};
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct TodoListInfo<'t> {
     name: Cow<'t, str>,
     id: Uuid,
+    #[serde(default)]
+    description: Cow<'t, str>,
+    #[serde(default)]
+    icon: Option<String>,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    owner: Option<User>,
+    #[serde(default)]
+    task_count: usize,
+    #[serde(default)]
+    done_count: usize,
+    #[serde(default)]
+    last_updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    revision: u64,
 }
 
 impl<'t> TodoListInfo<'t> {
@@ -25,6 +49,38 @@ impl<'t> TodoListInfo<'t> {
         &self.name
     }
 
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    pub fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    pub fn owner(&self) -> Option<&User> {
+        self.owner.as_ref()
+    }
+
+    pub const fn task_count(&self) -> usize {
+        self.task_count
+    }
+
+    pub const fn done_count(&self) -> usize {
+        self.done_count
+    }
+
+    pub const fn last_updated_at(&self) -> Option<DateTime<Utc>> {
+        self.last_updated_at
+    }
+
+    pub const fn revision(&self) -> u64 {
+        self.revision
+    }
+
     pub fn id_mut(&mut self) -> &mut Uuid {
         &mut self.id
     }
@@ -32,6 +88,21 @@ impl<'t> TodoListInfo<'t> {
     pub fn set_name<S: ToOwned<Owned = String>>(&mut self, name: S) {
         self.name = Cow::Owned(name.to_owned())
     }
+
+    pub fn into_owned(self) -> TodoListInfo<'static> {
+        TodoListInfo {
+            name: Cow::Owned(self.name.into_owned()),
+            description: Cow::Owned(self.description.into_owned()),
+            id: self.id,
+            icon: self.icon,
+            color: self.color,
+            owner: self.owner,
+            task_count: self.task_count,
+            done_count: self.done_count,
+            last_updated_at: self.last_updated_at,
+            revision: self.revision,
+        }
+    }
 }
 
 impl TodoListInfo<'static> {
@@ -39,19 +110,99 @@ impl TodoListInfo<'static> {
         Self {
             id,
             name: Cow::Owned(name),
+            description: Cow::Owned(String::new()),
+            icon: None,
+            color: None,
+            owner: None,
+            task_count: 0,
+            done_count: 0,
+            last_updated_at: None,
+            revision: 0,
+        }
+    }
+
+    /// Reads a list's info from the db without retrieving its tasks.
+    pub async fn from_redis(id: Uuid, pool: Pool) -> anyhow::Result<Self> {
+        let mut redis = pool.get().await?;
+        let info_json: String = redis
+            .json_get(id.to_string(), StoredTodoListInfo::PATHS.as_slice())
+            .await
+            .context("Failed to retrieve todo list info")?;
+        let stored = serde_json::from_str::<StoredTodoListInfo>(&info_json)
+            .context("Failed to parse todo list info")?;
+
+        Ok(stored.into_info(id))
+    }
+}
+
+/// Reply of a multi-path `JSON.GET` of a stored [`TodoList`]. Each path maps to its matches.
+#[derive(Deserialize)]
+struct StoredTodoListInfo {
+    #[serde(rename = "$.name")]
+    name: Vec<String>,
+    #[serde(rename = "$.description", default)]
+    description: Vec<String>,
+    #[serde(rename = "$.icon", default)]
+    icon: Vec<Option<String>>,
+    #[serde(rename = "$.color", default)]
+    color: Vec<Option<String>>,
+    #[serde(rename = "$.owner", default)]
+    owner: Vec<Option<User>>,
+    #[serde(rename = "$.lastUpdatedAt", default)]
+    last_updated_at: Vec<DateTime<Utc>>,
+    #[serde(rename = "$.tasks[*].done", default)]
+    done: Vec<bool>,
+    #[serde(rename = "$.revision", default)]
+    revision: Vec<u64>,
+}
+
+impl StoredTodoListInfo {
+    const PATHS: [&'static str; 8] = [
+        "$.name",
+        "$.description",
+        "$.icon",
+        "$.color",
+        "$.owner",
+        "$.lastUpdatedAt",
+        "$.tasks[*].done",
+        "$.revision",
+    ];
+
+    fn into_info(self, id: Uuid) -> TodoListInfo<'static> {
+        TodoListInfo {
+            id,
+            name: Cow::Owned(self.name.into_iter().next().unwrap_or_default()),
+            description: Cow::Owned(self.description.into_iter().next().unwrap_or_default()),
+            icon: self.icon.into_iter().next().flatten(),
+            color: self.color.into_iter().next().flatten(),
+            owner: self.owner.into_iter().next().flatten(),
+            task_count: self.done.len(),
+            done_count: self.done.iter().filter(|done| **done).count(),
+            last_updated_at: self.last_updated_at.into_iter().next(),
+            revision: self.revision.into_iter().next().unwrap_or_default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRedisValue, ToRedisArgs)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRedisValue, ToRedisArgs)]
 #[serde(rename_all = "camelCase")]
 pub struct TodoList {
     id: Uuid,
     name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    icon: Option<String>,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    owner: Option<User>,
     tasks: Vec<TodoTask>,
     created_at: DateTime<Utc>,
     last_updated_at: DateTime<Utc>,
-    connected_users: Vec<User>,
+    /// Number of commands applied to this list so far.
+    #[serde(default)]
+    revision: u64,
 }
 
 impl Default for TodoList {
@@ -59,10 +210,14 @@ impl Default for TodoList {
         Self {
             id: Uuid::new_v4(),
             name: String::new(),
+            description: String::new(),
+            icon: None,
+            color: None,
+            owner: None,
             tasks: vec![],
             created_at: Utc::now(),
             last_updated_at: Utc::now(),
-            connected_users: vec![],
+            revision: 0,
         }
     }
 }
@@ -75,6 +230,13 @@ impl TodoList {
         }
     }
 
+    pub fn owned_by(owner: User) -> Self {
+        Self {
+            owner: Some(owner),
+            ..Default::default()
+        }
+    }
+
     pub async fn from_redis(id: Uuid, pool: Pool) -> anyhow::Result<Self> {
         let mut redis = pool.get().await?;
         redis
@@ -93,6 +255,42 @@ impl TodoList {
 
     pub fn rename(&mut self, name: String) {
         self.name = name;
+        self.update_time();
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn set_description(&mut self, description: String) {
+        self.description = description;
+        self.update_time();
+    }
+
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    pub fn set_icon(&mut self, icon: Option<String>) {
+        self.icon = icon;
+        self.update_time();
+    }
+
+    pub fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    pub fn set_color(&mut self, color: Option<String>) {
+        self.color = color;
+        self.update_time();
+    }
+
+    pub fn owner(&self) -> Option<&User> {
+        self.owner.as_ref()
+    }
+
+    pub const fn last_updated_at(&self) -> DateTime<Utc> {
+        self.last_updated_at
     }
 
     fn update_time(&mut self) {
@@ -117,38 +315,175 @@ impl TodoList {
         self.tasks.iter_mut().find(|task| task.id() == id)
     }
 
-    pub async fn store(&self, pool: Pool) -> anyhow::Result<()> {
-        let mut redis = pool.get().await?;
-        redis.json_set(self.id.to_string(), "$", self).await?;
-        Ok(())
+    /// Blockers of `task` that are still open.
+    pub fn open_blockers(&self, task: Uuid) -> Vec<Uuid> {
+        self.task(task)
+            .map(|task| {
+                task.blocked_by()
+                    .iter()
+                    .copied()
+                    .filter(|blocker| self.task(*blocker).is_some_and(|b| !b.is_done()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether `task` is blocked, directly or transitively, by `blocker`.
+    pub fn depends_on(&self, task: Uuid, blocker: Uuid) -> bool {
+        let mut to_visit = vec![task];
+        let mut visited = vec![];
+        while let Some(current) = to_visit.pop() {
+            if current == blocker {
+                return true;
+            }
+            if visited.contains(&current) {
+                continue;
+            }
+            visited.push(current);
+            if let Some(task) = self.task(current) {
+                to_visit.extend_from_slice(task.blocked_by());
+            }
+        }
+
+        false
+    }
+
+    pub const fn revision(&self) -> u64 {
+        self.revision
     }
 
-    pub fn connected_users(&self) -> &[User] {
-        &self.connected_users[..]
+    pub fn bump_revision(&mut self) -> u64 {
+        self.revision += 1;
+        self.revision
     }
 
-    pub fn add_user(&mut self, user: User) {
-        self.connected_users.push(user);
+    /// Open tasks blocked by `blocker` that have no other open blocker, i.e. that become
+    /// actionable once `blocker` is done.
+    pub fn unblocked_by(&self, blocker: Uuid) -> Vec<Uuid> {
+        self.tasks
+            .iter()
+            .filter(|task| !task.is_done() && task.blocked_by().contains(&blocker))
+            .map(TodoTask::id)
+            .filter(|task| self.open_blockers(*task).is_empty())
+            .collect()
     }
 
-    pub fn remove_user(&mut self, id: Uuid) {
-        self.connected_users.retain(|user| user.id() != &id);
+    pub async fn store(&self, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis
+            .json_set::<_, _, _, ()>(self.id.to_string(), "$", self)
+            .await?;
+        Ok(())
     }
 
     pub fn as_info(&self) -> TodoListInfo<'_> {
         TodoListInfo {
             id: self.id,
             name: Cow::Borrowed(self.name()),
+            description: Cow::Borrowed(self.description()),
+            icon: self.icon.clone(),
+            color: self.color.clone(),
+            owner: self.owner.clone(),
+            task_count: self.tasks.len(),
+            done_count: self.tasks.iter().filter(|task| task.is_done()).count(),
+            last_updated_at: Some(self.last_updated_at),
+            revision: self.revision,
+        }
+    }
+
+    /// Creates a new list with the same name and tasks as this one.
+    /// Tasks get fresh ids.
+    pub fn duplicate(&self, options: CloneOptions, issuer: &User) -> Self {
+        let mut tasks = self
+            .tasks
+            .iter()
+            .map(|task| {
+                let mut task = task.duplicate();
+                if options.reset_done {
+                    task.set_done(false);
+                }
+                if options.reset_assignees {
+                    task.assign_to(issuer.clone());
+                }
+                task
+            })
+            .collect::<Vec<_>>();
+
+        let new_ids = self
+            .tasks
+            .iter()
+            .zip(tasks.iter())
+            .map(|(old, new)| (old.id(), new.id()))
+            .collect::<HashMap<_, _>>();
+        // Blockers that aren't in the list anymore are dropped
+        for task in tasks.iter_mut() {
+            task.blocked_by = task
+                .blocked_by
+                .iter()
+                .filter_map(|blocker| new_ids.get(blocker).copied())
+                .collect();
+        }
+
+        Self {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            icon: self.icon.clone(),
+            color: self.color.clone(),
+            owner: Some(issuer.clone()),
+            ..Self::new(tasks)
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToRedisArgs, FromRedisValue)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct CloneOptions {
+    pub reset_done: bool,
+    pub reset_assignees: bool,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToRedisArgs, FromRedisValue)]
 pub struct TodoTask {
     id: Uuid,
     name: String,
+    #[serde(default)]
+    description: String,
     assignee: User,
     done: bool,
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[schemars(with = "Option<u64>")]
+    estimate: Option<Duration>,
+    #[serde(default)]
+    blocked_by: Vec<Uuid>,
+    created_at: CreationTime,
+    /// Revision of the list at which this task was last edited.
+    #[serde(default)]
+    revision: u64,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    text_log: TextLog,
+}
+
+/// When a task was created. Tasks stored before creation times were recorded are dated from
+/// when they are loaded. This isn't a serde default, so the schema still requires the field.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(transparent)]
+struct CreationTime(DateTime<Utc>);
+
+impl CreationTime {
+    fn now() -> Self {
+        Self(Utc::now())
+    }
+}
+
+impl<'de> Deserialize<'de> for CreationTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Missing fields deserialize as `None`, the way they do for `Option` fields
+        let time = Option::<DateTime<Utc>>::deserialize(deserializer)?;
+        Ok(time.map_or_else(Self::now, Self))
+    }
 }
 
 impl TodoTask {
@@ -156,8 +491,26 @@ impl TodoTask {
         Self {
             id: Uuid::new_v4(),
             name: String::new(),
+            description: String::new(),
             assignee,
             done: false,
+            estimate: None,
+            blocked_by: vec![],
+            created_at: CreationTime::now(),
+            revision: 0,
+            updated_at: None,
+            text_log: TextLog::default(),
+        }
+    }
+
+    pub fn duplicate(&self) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            created_at: CreationTime::now(),
+            revision: 0,
+            updated_at: None,
+            text_log: TextLog::default(),
+            ..self.clone()
         }
     }
 
@@ -169,6 +522,10 @@ impl TodoTask {
         self.name.as_str()
     }
 
+    pub fn description(&self) -> &str {
+        self.description.as_str()
+    }
+
     pub const fn assignee(&self) -> &User {
         &self.assignee
     }
@@ -177,6 +534,28 @@ impl TodoTask {
         self.done
     }
 
+    pub const fn created_at(&self) -> DateTime<Utc> {
+        self.created_at.0
+    }
+
+    pub const fn estimate(&self) -> Option<Duration> {
+        self.estimate
+    }
+
+    pub const fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// When this task was last edited, if ever.
+    pub const fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+
+    pub fn touch(&mut self, revision: u64) {
+        self.revision = revision;
+        self.updated_at = Some(Utc::now());
+    }
+
     pub fn assign_to(&mut self, assignee: User) {
         self.assignee = assignee;
     }
@@ -188,4 +567,77 @@ impl TodoTask {
     pub fn rename(&mut self, name: String) {
         self.name = name;
     }
+
+    /// Replaces one of the task's texts by `text` at the list's `revision`.
+    pub fn replace_text(&mut self, field: TextField, text: String, revision: u64) {
+        let current = match field {
+            TextField::Name => &mut self.name,
+            TextField::Description => &mut self.description,
+        };
+        self.text_log
+            .replace(field, current, &text, self.revision, revision);
+        *current = text;
+    }
+
+    /// Merges `edit` into one of the task's texts at the list's `revision`.
+    pub fn edit_text(&mut self, edit: TextEdit, revision: u64) -> CommandResult {
+        let text = match edit.field {
+            TextField::Name => &mut self.name,
+            TextField::Description => &mut self.description,
+        };
+        self.text_log.apply(text, edit, self.revision, revision)
+    }
+
+    pub fn set_estimate(&mut self, estimate: Option<Duration>) {
+        self.estimate = estimate;
+    }
+
+    pub fn blocked_by(&self) -> &[Uuid] {
+        &self.blocked_by[..]
+    }
+
+    pub fn add_blocker(&mut self, blocker: Uuid) {
+        if !self.blocked_by.contains(&blocker) {
+            self.blocked_by.push(blocker);
+        }
+    }
+
+    pub fn remove_blocker(&mut self, blocker: Uuid) {
+        self.blocked_by.retain(|id| *id != blocker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::user::User;
+
+    use super::{CloneOptions, TodoList, TodoTask};
+
+    #[tokio::test]
+    async fn tasks_stored_without_creation_time_are_dated_when_loaded() {
+        let mut task = serde_json::to_value(TodoTask::new(User::new().await)).unwrap();
+        task.as_object_mut().unwrap().remove("created_at");
+        let before = chrono::Utc::now();
+        let task: TodoTask = serde_json::from_value(task).unwrap();
+        assert!(task.created_at() >= before);
+    }
+
+    #[tokio::test]
+    async fn duplicates_drop_blockers_missing_from_the_list() {
+        let user = User::new().await;
+        let mut blocker = TodoTask::new(user.clone());
+        let mut blocked = TodoTask::new(user.clone());
+        blocked.add_blocker(blocker.id());
+        blocked.add_blocker(Uuid::new_v4());
+        blocker.add_blocker(Uuid::new_v4());
+        let todo = TodoList::new(vec![blocker, blocked]);
+
+        let duplicate = todo.duplicate(CloneOptions::default(), &user);
+
+        let (blocker, blocked) = (&duplicate.tasks()[0], &duplicate.tasks()[1]);
+        assert!(blocker.blocked_by().is_empty());
+        assert_eq!(blocked.blocked_by(), &[blocker.id()]);
+    }
 }
@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{Command, CommandError, TaskCommand, TaskCommandMeta, TodoList};
+
+/// Where a command made while offline comes from.
+#[derive(Debug, Clone, Copy)]
+pub struct OfflineOrigin {
+    /// Revision of the list the client had when going offline.
+    pub base_revision: u64,
+    /// When the command was made, according to the client's clock.
+    pub issued_at: DateTime<Utc>,
+}
+
+impl Command {
+    /// Decides how a command made offline is merged into the list, which may have changed
+    /// since the client went offline. Returns whether the command has to be adapted to the
+    /// current state of the list, or the reason why it can't be merged:
+    ///
+    /// - commands to what didn't change since the base revision are applied as is;
+    /// - commands to unknown tasks are rejected;
+    /// - text edits are rebased on the edits made in the meantime. Edits aren't stored, so
+    ///   text edits to a task changed since the base revision are rejected as conflicting
+    ///   if the list has been unloaded in the meantime;
+    /// - adding tasks, blockers and timers commute with any other change;
+    /// - commands setting a value that has been changed online after the command was issued
+    ///   are rejected, so that the most recent change wins. List settings all share the
+    ///   list's last update time.
+    pub fn rebase(&self, todo: &TodoList, origin: OfflineOrigin) -> Result<bool, CommandError> {
+        let changed_at = match self {
+            Command::CreateTask | Command::ClaimTask(_) | Command::ReleaseTask(_) => {
+                return Ok(false)
+            }
+            Command::TaskCommand(TaskCommandMeta { task, command }) => {
+                let Some(task) = todo.task(*task) else {
+                    return Err(CommandError::UnknownTask { task: *task });
+                };
+                if task.revision() <= origin.base_revision {
+                    return Ok(false);
+                }
+                match command {
+                    TaskCommand::EditText(_) => return Ok(true),
+                    TaskCommand::AddBlocker(_)
+                    | TaskCommand::RemoveBlocker(_)
+                    | TaskCommand::StartTimer
+                    | TaskCommand::StopTimer => return Ok(false),
+                    TaskCommand::SetDone(_)
+                    | TaskCommand::Rename(_)
+                    | TaskCommand::SetDescription(_)
+                    | TaskCommand::SetAssignee(_)
+                    | TaskCommand::SetEstimate(_) => task.updated_at(),
+                }
+            }
+            Command::SetListName(_)
+            | Command::SetListDescription(_)
+            | Command::SetListIcon(_)
+            | Command::SetListColor(_) => {
+                if todo.revision() <= origin.base_revision {
+                    return Ok(false);
+                }
+                Some(todo.last_updated_at())
+            }
+        };
+        match changed_at {
+            Some(changed_at) if changed_at > origin.issued_at => {
+                Err(CommandError::Superseded { changed_at })
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Makes the command refer to task `to` instead of `from`, e.g. to the task the server
+    /// created for a task created offline.
+    pub fn replace_task(&mut self, from: Uuid, to: Uuid) {
+        let replace = |task: &mut Uuid| {
+            if *task == from {
+                *task = to;
+            }
+        };
+        match self {
+            Command::TaskCommand(TaskCommandMeta { task, command }) => {
+                replace(task);
+                if let TaskCommand::AddBlocker(blocker) | TaskCommand::RemoveBlocker(blocker) =
+                    command
+                {
+                    replace(blocker);
+                }
+            }
+            Command::ClaimTask(task) | Command::ReleaseTask(task) => replace(task),
+            Command::CreateTask
+            | Command::SetListName(_)
+            | Command::SetListDescription(_)
+            | Command::SetListIcon(_)
+            | Command::SetListColor(_) => (),
+        }
+    }
+}
@@ -1,13 +1,48 @@
+mod chat;
 mod command;
 mod handle;
+mod lease;
 mod list;
+mod merge;
+mod patch;
+mod presence;
+mod stats;
 mod task;
+mod template;
+mod text;
+mod timer;
+mod visit;
 
 pub type TodoCommandReceiver = tokio::sync::mpsc::Receiver<TodoCommand>;
 pub type TodoCommandSender = tokio::sync::mpsc::Sender<TodoCommand>;
 pub type TodoListWatcher = tokio::sync::watch::Receiver<TodoList>;
 pub type TodoListUpdater = tokio::sync::watch::Sender<TodoList>;
+pub type TodoListChangeSender = tokio::sync::broadcast::Sender<TodoListChange>;
+pub type TodoListChangeReceiver = tokio::sync::broadcast::Receiver<TodoListChange>;
+pub type ChatSender = tokio::sync::broadcast::Sender<ChatEvent>;
+pub type ChatReceiver = tokio::sync::broadcast::Receiver<ChatEvent>;
+pub type PresenceReceiver = tokio::sync::mpsc::Receiver<PresenceUpdate>;
+pub type PresenceSender = tokio::sync::mpsc::Sender<PresenceUpdate>;
+pub type PresenceWatcher = tokio::sync::watch::Receiver<TodoListPresence>;
+pub type PresenceUpdater = tokio::sync::watch::Sender<TodoListPresence>;
 
-pub use command::{Command, TaskCommand, TaskCommandMeta, TodoCommand};
-pub use handle::TodoListHandle;
-pub use list::{TodoList, TodoListInfo};
+pub use chat::{
+    BlockedWords, Chat, ChatCommand, ChatEvent, ChatMessage, ChatModerator, ChatPage, ChatRequest,
+};
+pub use command::{
+    AppliedCommand, Command, CommandError, CommandReply, CommandRequest, CommandResult,
+    TaskCommand, TaskCommandMeta, TodoCommand,
+};
+pub use handle::{TodoListConnection, TodoListHandle};
+pub use lease::TaskLease;
+pub use list::{CloneOptions, TodoList, TodoListInfo};
+pub use merge::OfflineOrigin;
+pub use patch::{
+    TodoListChange, TodoListDiffer, TodoListFollower, TodoListHistory, TodoListUpdate,
+};
+pub use presence::{PresenceState, PresenceStatus, PresenceUpdate, TodoListPresence, UserPresence};
+pub use stats::{AssigneeStats, TodoListStats};
+pub use template::TodoTemplate;
+pub use text::{TextEdit, TextField, TextOperation};
+pub use timer::{ListTimeReport, TaskTimeReport, TimeEntry, UserTimeReport};
+pub use visit::ListVisit;
@@ -0,0 +1,254 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use json_patch::Patch;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{TodoList, TodoListStats};
+
+/// What a client needs to bring its copy of a list up to date.
+#[derive(Debug, Clone)]
+pub enum TodoListUpdate {
+    /// The client needs the whole list.
+    Snapshot,
+    Patch {
+        /// Revision of the list once the patch has been applied.
+        revision: u64,
+        /// RFC 6902 operations, relative to the previously sent state.
+        ops: Patch,
+    },
+}
+
+/// A change applied to a list, pushed to every connection.
+#[derive(Debug, Clone)]
+pub struct TodoListChange {
+    /// Revision of the list once changed.
+    pub revision: u64,
+    /// What brings a copy of the list at the previous revision up to date.
+    pub update: TodoListUpdate,
+    /// Tasks whose blockers have all been completed by the change.
+    pub unblocked: Vec<Uuid>,
+    /// Stats of the list once changed.
+    pub stats: TodoListStats,
+}
+
+/// Remembers the last state of a list and diffs new states against it.
+///
+/// Each list keeps a single differ, so that a change is diffed once however many clients
+/// are connected. Its patches are broadcast in [`TodoListChange`]s.
+#[derive(Debug, Default)]
+pub struct TodoListDiffer {
+    last: Option<Value>,
+}
+
+impl TodoListDiffer {
+    /// Records `todo_list` as already known, so that the next update is a patch against it.
+    pub fn sync(&mut self, todo_list: &TodoList) -> anyhow::Result<()> {
+        let current = serde_json::to_value(todo_list).context("Failed to serialize todo list")?;
+        self.last = Some(current);
+        Ok(())
+    }
+
+    /// Asks for a full snapshot the first time, then returns only the changes. `None` if nothing changed.
+    /// After a failure, the next update is a snapshot again.
+    pub fn update(&mut self, todo_list: &TodoList) -> anyhow::Result<Option<TodoListUpdate>> {
+        let current = match serde_json::to_value(todo_list) {
+            Ok(current) => current,
+            Err(e) => {
+                self.last = None;
+                return Err(e).context("Failed to serialize todo list");
+            }
+        };
+        let update = match self.last.as_ref() {
+            None => Some(TodoListUpdate::Snapshot),
+            Some(last) => {
+                let ops = json_patch::diff(last, &current);
+                (!ops.0.is_empty()).then(|| TodoListUpdate::Patch {
+                    revision: todo_list.revision(),
+                    ops,
+                })
+            }
+        };
+        self.last = Some(current);
+
+        Ok(update)
+    }
+}
+
+/// Follows the changes of a list on behalf of a client, telling what it needs to stay up to date.
+#[derive(Debug)]
+pub struct TodoListFollower {
+    /// Revision of the list last sent to the client.
+    revision: Option<u64>,
+    patches: bool,
+}
+
+impl TodoListFollower {
+    /// Follows a list for a client that is sent patches, or only snapshots.
+    pub const fn new(patches: bool) -> Self {
+        Self {
+            revision: None,
+            patches,
+        }
+    }
+
+    /// Records that the client has been sent the list as of `revision`.
+    pub fn sent(&mut self, revision: u64) {
+        self.revision = Some(revision);
+    }
+
+    /// What the client needs after `change`, or `None` if it already has it. Changes
+    /// that don't directly follow what the client has, e.g. because some were skipped
+    /// while it lagged behind, ask for a snapshot.
+    pub fn follow(&mut self, change: &TodoListChange) -> Option<TodoListUpdate> {
+        if self.revision.is_some_and(|sent| change.revision <= sent) {
+            return None;
+        }
+        match &change.update {
+            TodoListUpdate::Patch { revision, ops }
+                if self.patches && self.revision.map(|sent| sent + 1) == Some(*revision) =>
+            {
+                self.revision = Some(*revision);
+                Some(TodoListUpdate::Patch {
+                    revision: *revision,
+                    ops: ops.clone(),
+                })
+            }
+            _ => Some(TodoListUpdate::Snapshot),
+        }
+    }
+}
+
+/// The latest changes applied to a list, so that reconnecting clients can catch up
+/// without being sent the whole list again.
+#[derive(Debug, Clone)]
+pub struct TodoListHistory {
+    changes: Arc<Mutex<VecDeque<(u64, Patch)>>>,
+    capacity: usize,
+}
+
+impl TodoListHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            changes: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Records the changes that brought the list to `revision`, evicting the oldest ones.
+    pub fn record(&self, revision: u64, ops: Patch) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut changes = self.changes.lock().expect("History lock poisoned");
+        if changes.len() == self.capacity {
+            changes.pop_front();
+        }
+        changes.push_back((revision, ops));
+    }
+
+    /// Forgets every change, e.g. after one that couldn't be recorded.
+    pub fn clear(&self) {
+        self.changes.lock().expect("History lock poisoned").clear();
+    }
+
+    /// Changes needed to go from `revision` to `current`, or `None` if some of them
+    /// are no longer available.
+    pub fn since(&self, revision: u64, current: u64) -> Option<Vec<TodoListUpdate>> {
+        if revision == current {
+            return Some(vec![]);
+        }
+        if revision > current {
+            return None;
+        }
+        let changes = self.changes.lock().expect("History lock poisoned");
+        let (oldest, _) = changes.front()?;
+        if *oldest > revision + 1 {
+            return None;
+        }
+
+        let updates = changes
+            .iter()
+            .filter(|(change_revision, _)| *change_revision > revision)
+            .map(|(revision, ops)| TodoListUpdate::Patch {
+                revision: *revision,
+                ops: ops.clone(),
+            })
+            .collect();
+        Some(updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json_patch::Patch;
+
+    use super::{
+        TodoList, TodoListChange, TodoListFollower, TodoListHistory, TodoListStats, TodoListUpdate,
+    };
+
+    fn patch(revision: u64) -> TodoListChange {
+        TodoListChange {
+            revision,
+            update: TodoListUpdate::Patch {
+                revision,
+                ops: Patch(vec![]),
+            },
+            unblocked: vec![],
+            stats: TodoListStats::new(&TodoList::default()),
+        }
+    }
+
+    #[test]
+    fn followers_only_get_patches_following_what_they_have() {
+        let mut follower = TodoListFollower::new(true);
+        follower.sent(2);
+
+        assert!(follower.follow(&patch(2)).is_none());
+        assert!(matches!(
+            follower.follow(&patch(3)),
+            Some(TodoListUpdate::Patch { revision: 3, .. })
+        ));
+        assert!(matches!(
+            follower.follow(&patch(5)),
+            Some(TodoListUpdate::Snapshot)
+        ));
+        follower.sent(6);
+        assert!(follower.follow(&patch(6)).is_none());
+
+        let mut follower = TodoListFollower::new(false);
+        follower.sent(2);
+        assert!(matches!(
+            follower.follow(&patch(3)),
+            Some(TodoListUpdate::Snapshot)
+        ));
+    }
+
+    #[test]
+    fn history_only_returns_contiguous_changes() {
+        let history = TodoListHistory::new(3);
+        for revision in 1..=5 {
+            history.record(revision, Patch(vec![]));
+        }
+
+        assert!(history
+            .since(5, 5)
+            .is_some_and(|updates| updates.is_empty()));
+        assert!(history
+            .since(2, 5)
+            .is_some_and(|updates| updates.len() == 3));
+        assert!(history
+            .since(4, 5)
+            .is_some_and(|updates| updates.len() == 1));
+        assert!(history.since(1, 5).is_none());
+        assert!(history.since(6, 5).is_none());
+    }
+}
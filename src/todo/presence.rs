@@ -0,0 +1,104 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::user::User;
+
+use super::TaskLease;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    #[default]
+    Active,
+    Idle,
+}
+
+/// What a connected user is up to, as reported by their client.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct PresenceState {
+    pub status: PresenceStatus,
+    /// Task the user is looking at.
+    pub viewing: Option<Uuid>,
+    /// Task the user is editing.
+    pub editing: Option<Uuid>,
+    pub typing: bool,
+}
+
+impl PresenceState {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct UserPresence {
+    pub user: User,
+    #[serde(flatten)]
+    pub state: PresenceState,
+    /// Task the user is allowed to edit exclusively.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease: Option<TaskLease>,
+}
+
+/// Users connected to a list. Never stored: presence only lives as long as the list's task.
+pub type TodoListPresence = Vec<UserPresence>;
+
+/// Changes to a list's presence, handled by the list's task.
+#[derive(Debug)]
+pub enum PresenceUpdate {
+    /// A user connected, or reconnected within the presence grace period.
+    Join(User),
+    Update {
+        user: Uuid,
+        state: PresenceState,
+    },
+    Leave(Uuid),
+    /// One of a user's connections closed, releasing the leases claimed through it.
+    Disconnect(Uuid),
+}
+
+impl PresenceUpdate {
+    /// Returns whether `presence` has changed.
+    pub fn apply(self, presence: &mut TodoListPresence) -> bool {
+        match self {
+            PresenceUpdate::Join(user) => {
+                match presence.iter_mut().find(|p| p.user.id() == user.id()) {
+                    Some(existing) if existing.user == user && existing.state.is_default() => false,
+                    // Leases are kept, as they may be held through another connection
+                    Some(existing) => {
+                        existing.user = user;
+                        existing.state = PresenceState::default();
+                        true
+                    }
+                    None => {
+                        presence.push(UserPresence {
+                            user,
+                            state: PresenceState::default(),
+                            lease: None,
+                        });
+                        true
+                    }
+                }
+            }
+            PresenceUpdate::Update { user, state } => {
+                match presence.iter_mut().find(|p| *p.user.id() == user) {
+                    Some(existing) if existing.state != state => {
+                        existing.state = state;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            PresenceUpdate::Leave(user) => {
+                let before = presence.len();
+                presence.retain(|p| *p.user.id() != user);
+                presence.len() != before
+            }
+            PresenceUpdate::Disconnect(connection) => {
+                super::lease::release_connection(presence, connection)
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::user::User;
+
+use super::list::TodoList;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssigneeStats {
+    pub user: User,
+    pub open: usize,
+    pub done: usize,
+}
+
+/// Summary of a list's progress, computed once per change and pushed along with it.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoListStats {
+    pub total: usize,
+    pub open: usize,
+    /// Open tasks that are waiting on other open tasks.
+    pub blocked: usize,
+    pub done: usize,
+    pub completion_rate: f64,
+    pub assignees: Vec<AssigneeStats>,
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[schemars(with = "Option<u64>")]
+    pub oldest_open_task_age: Option<Duration>,
+}
+
+impl TodoListStats {
+    /// Goes through the tasks once, after gathering the open ones to tell blocked tasks.
+    pub fn new(list: &TodoList) -> Self {
+        let tasks = list.tasks();
+        let open_tasks = tasks
+            .iter()
+            .filter(|task| !task.is_done())
+            .map(|task| task.id())
+            .collect::<HashSet<_>>();
+
+        let (mut done, mut blocked) = (0, 0);
+        let mut oldest_open_task: Option<DateTime<Utc>> = None;
+        let mut assignees: Vec<AssigneeStats> = vec![];
+        let mut assignee_indices = HashMap::new();
+        for task in tasks {
+            let index = *assignee_indices
+                .entry(*task.assignee().id())
+                .or_insert_with(|| {
+                    assignees.push(AssigneeStats {
+                        user: task.assignee().clone(),
+                        open: 0,
+                        done: 0,
+                    });
+                    assignees.len() - 1
+                });
+            let stats = &mut assignees[index];
+            if task.is_done() {
+                done += 1;
+                stats.done += 1;
+                continue;
+            }
+            stats.open += 1;
+            if task
+                .blocked_by()
+                .iter()
+                .any(|blocker| open_tasks.contains(blocker))
+            {
+                blocked += 1;
+            }
+            oldest_open_task = Some(match oldest_open_task {
+                Some(oldest) => oldest.min(task.created_at()),
+                None => task.created_at(),
+            });
+        }
+
+        let total = tasks.len();
+        Self {
+            total,
+            open: total - done,
+            blocked,
+            done,
+            completion_rate: if total == 0 {
+                0.0
+            } else {
+                done as f64 / total as f64
+            },
+            assignees,
+            oldest_open_task_age: oldest_open_task
+                .map(|created_at| (Utc::now() - created_at).to_std().unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        todo::{list::TodoTask, TodoList},
+        user::User,
+    };
+
+    use super::TodoListStats;
+
+    #[tokio::test]
+    async fn only_tasks_waiting_on_open_tasks_are_blocked() {
+        let user = User::new().await;
+        let mut done = TodoTask::new(user.clone());
+        done.set_done(true);
+        let open = TodoTask::new(user.clone());
+        let mut blocked = TodoTask::new(user.clone());
+        blocked.add_blocker(open.id());
+        blocked.add_blocker(done.id());
+        let mut unblocked = TodoTask::new(user.clone());
+        unblocked.add_blocker(done.id());
+        let todo = TodoList::new(vec![done, open, blocked, unblocked]);
+
+        let stats = TodoListStats::new(&todo);
+
+        assert_eq!((stats.total, stats.open, stats.done), (4, 3, 1));
+        assert_eq!(stats.blocked, 1);
+        assert_eq!(stats.assignees.len(), 1);
+        assert_eq!((stats.assignees[0].open, stats.assignees[0].done), (3, 1));
+    }
+}
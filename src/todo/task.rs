@@ -2,11 +2,28 @@ use deadpool_redis::Pool;
 use std::time::Duration;
 use uuid::Uuid;
 
+use crate::{
+    notification::{Notification, NotificationKind, Notifier},
+    user::User,
+};
+
 use super::{
     command::{Applicable, TodoCommand},
-    TodoCommandReceiver, TodoList, TodoListUpdater,
+    lease, AppliedCommand, Command, CommandError, CommandReply, OfflineOrigin, PresenceReceiver,
+    PresenceUpdater, TaskCommand, TaskCommandMeta, TextField, TimeEntry, TodoCommandReceiver,
+    TodoList, TodoListChange, TodoListChangeSender, TodoListDiffer, TodoListHistory,
+    TodoListPresence, TodoListStats, TodoListUpdate, TodoListUpdater,
 };
 
+/// What the list's task works with besides the list and its presence.
+pub struct TodoListContext {
+    pub pool: Pool,
+    pub notifier: Notifier,
+    pub history: TodoListHistory,
+    /// How long edit leases last unless renewed.
+    pub lease_duration: Duration,
+}
+
 #[tracing::instrument(
     name = "Todo list handler",
     skip_all,
@@ -15,23 +32,341 @@ use super::{
 pub async fn todo_list_task(
     todo_id: Uuid,
     updater: TodoListUpdater,
+    changes: TodoListChangeSender,
     mut commands: TodoCommandReceiver,
-    pool: Pool,
-    _store_interval: Duration,
+    presence: PresenceUpdater,
+    mut presence_updates: PresenceReceiver,
+    context: TodoListContext,
 ) {
     tracing::info!("Spawned successfully!");
 
-    while let Some(command) = commands.recv().await {
-        tracing::debug!("Got command {:?}", &command);
-        updater.send_modify(|todo| {
-            let TodoCommand { issuer, command } = command;
-            command.apply(todo, issuer);
-        });
+    let mut differ = TodoListDiffer::default();
+    if let Err(e) = differ.sync(&updater.borrow()) {
+        tracing::error!("Failed to initialize differ: {e:?}");
+    }
+
+    loop {
+        let next_expiry = lease::next_expiry(&presence.borrow());
+        tokio::select! {
+            command = commands.recv() => {
+                let Some(command) = command else { break };
+                tracing::debug!("Got command {:?}", &command);
+                let leases = Leases { presence: &presence, duration: context.lease_duration };
+                handle_command(todo_id, command, &updater, &changes, leases, &context, &mut differ).await;
+            },
+            Some(update) = presence_updates.recv() => {
+                tracing::debug!("Got presence update {:?}", &update);
+                presence.send_if_modified(|presence| update.apply(presence));
+            },
+            _ = tokio::time::sleep(next_expiry.unwrap_or_default()), if next_expiry.is_some() => {
+                presence.send_if_modified(lease::expire);
+            },
+        }
     }
 
     tracing::info!("Closing todo list");
     let todo_list = updater.send_replace(TodoList::default());
-    if todo_list.store(pool).await.is_err() {
+    if todo_list.store(context.pool).await.is_err() {
         tracing::error!("Failed to store list!")
     }
 }
+
+/// Edit leases on the list's tasks, kept along with its presence.
+struct Leases<'p> {
+    presence: &'p PresenceUpdater,
+    duration: Duration,
+}
+
+async fn handle_command(
+    todo_id: Uuid,
+    command: TodoCommand,
+    updater: &TodoListUpdater,
+    changes: &TodoListChangeSender,
+    leases: Leases<'_>,
+    context: &TodoListContext,
+    differ: &mut TodoListDiffer,
+) {
+    let TodoCommand {
+        issuer,
+        command,
+        reply,
+        expected_revision,
+        offline,
+        connection,
+    } = command;
+    let result = match command {
+        Command::ClaimTask(task) => update_leases(updater, &leases, task, |presence| {
+            lease::claim(presence, task, &issuer, connection, leases.duration).map(|()| true)
+        }),
+        Command::ReleaseTask(task) => update_leases(updater, &leases, task, |presence| {
+            Ok(lease::release(presence, task, &issuer))
+        }),
+        Command::TaskCommand(
+            task_command @ TaskCommandMeta {
+                command: TaskCommand::StartTimer | TaskCommand::StopTimer,
+                ..
+            },
+        ) => {
+            track_time(
+                todo_id,
+                &task_command,
+                &issuer,
+                updater,
+                context.pool.clone(),
+            )
+            .await
+        }
+        command => match command.edited_task().map_or(Ok(()), |task| {
+            lease::check(&leases.presence.borrow(), task, &issuer)
+        }) {
+            Err(e) => Err(e),
+            Ok(()) => {
+                let before = Notable::changed_by(&updater.borrow(), &command);
+                let mut result = None;
+                let mut update = None;
+                updater.send_if_modified(|todo| {
+                    let reply =
+                        apply_command(todo, command, issuer.clone(), expected_revision, offline);
+                    let modified = reply.is_ok();
+                    if modified {
+                        update = Some((diff(differ, todo), TodoListStats::new(todo)));
+                    }
+                    result = Some(reply);
+                    modified
+                });
+                let result = result.expect("send_if_modified always runs its closure");
+                if let (Ok(applied), Some((update, stats))) = (&result, update) {
+                    match &update {
+                        TodoListUpdate::Patch { revision, ops } => {
+                            context.history.record(*revision, ops.clone());
+                        }
+                        // Clients can't catch up through a gap in the history
+                        TodoListUpdate::Snapshot => context.history.clear(),
+                    }
+                    // Nobody might be listening
+                    let _ = changes.send(TodoListChange {
+                        revision: applied.revision,
+                        update,
+                        unblocked: applied.unblocked.clone(),
+                        stats,
+                    });
+                }
+                let after = before
+                    .as_ref()
+                    .and_then(|before| Notable::of(&updater.borrow(), before.task));
+                if let (Ok(_), Some(before), Some(after)) = (&result, before, after) {
+                    // Storing notifications mustn't hold up the list
+                    let notifier = context.notifier.clone();
+                    let issuer = issuer.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = notify(todo_id, &before, &after, &issuer, &notifier).await {
+                            tracing::error!("Failed to send notifications: {e:?}");
+                        }
+                    });
+                }
+                result
+            }
+        },
+    };
+    if let Err(e) = &result {
+        tracing::debug!("Command rejected: {e}");
+    }
+    if let Some(reply) = reply {
+        let _ = reply.send(result);
+    }
+}
+
+/// Claims or releases a lease on `task`, leaving the list untouched.
+/// `update` returns whether the leases changed.
+fn update_leases(
+    updater: &TodoListUpdater,
+    leases: &Leases,
+    task: Uuid,
+    update: impl FnOnce(&mut TodoListPresence) -> Result<bool, CommandError>,
+) -> CommandReply {
+    let revision = {
+        let todo = updater.borrow();
+        if todo.task(task).is_none() {
+            return Err(CommandError::UnknownTask { task });
+        }
+        todo.revision()
+    };
+    let mut result = None;
+    leases.presence.send_if_modified(|presence| {
+        let updated = update(presence);
+        let modified = updated == Ok(true);
+        result = Some(updated);
+        modified
+    });
+    result.expect("send_if_modified always runs its closure")?;
+
+    Ok(AppliedCommand {
+        revision,
+        transformed: false,
+        unblocked: vec![],
+        created: None,
+    })
+}
+
+fn apply_command(
+    todo: &mut TodoList,
+    command: Command,
+    issuer: User,
+    expected_revision: Option<u64>,
+    offline: Option<OfflineOrigin>,
+) -> CommandReply {
+    let mut transformed = false;
+    if let Some(origin) = offline {
+        transformed = command.rebase(todo, origin)?;
+    } else if let Some(expected_revision) = expected_revision {
+        command.check_revision(todo, expected_revision)?;
+    }
+    let edited_task = command.edited_task();
+    let completed_task = match &command {
+        Command::TaskCommand(TaskCommandMeta {
+            task,
+            command: TaskCommand::SetDone(true),
+        }) => Some(*task),
+        _ => None,
+    };
+    let creates_task = matches!(command, Command::CreateTask);
+    command.apply(todo, issuer)?;
+    let revision = todo.bump_revision();
+    if let Some(task) = edited_task.and_then(|task| todo.task_mut(task)) {
+        task.touch(revision);
+    }
+    let unblocked = completed_task.map_or(vec![], |task| todo.unblocked_by(task));
+    // Tasks are created at the end of the list
+    let created = creates_task
+        .then(|| todo.tasks().last().map(|task| task.id()))
+        .flatten();
+
+    Ok(AppliedCommand {
+        revision,
+        transformed,
+        unblocked,
+        created,
+    })
+}
+
+/// What of a task is worth notifying users about when it changes.
+struct Notable {
+    task: Uuid,
+    name: String,
+    description: String,
+    assignee: User,
+}
+
+impl Notable {
+    fn of(todo: &TodoList, task: Uuid) -> Option<Self> {
+        let task = todo.task(task)?;
+        Some(Self {
+            task: task.id(),
+            name: task.name().to_owned(),
+            description: task.description().to_owned(),
+            assignee: task.assignee().clone(),
+        })
+    }
+
+    /// The task `command` may change in a notable way, as it currently is.
+    fn changed_by(todo: &TodoList, command: &Command) -> Option<Self> {
+        match command {
+            Command::TaskCommand(TaskCommandMeta {
+                task,
+                command:
+                    TaskCommand::Rename(_)
+                    | TaskCommand::SetDescription(_)
+                    | TaskCommand::EditText(_)
+                    | TaskCommand::SetAssignee(_),
+            }) => Self::of(todo, *task),
+            _ => None,
+        }
+    }
+}
+
+/// Notifies users newly mentioned in a task, or it has been assigned to.
+async fn notify(
+    todo_id: Uuid,
+    before: &Notable,
+    after: &Notable,
+    issuer: &User,
+    notifier: &Notifier,
+) -> anyhow::Result<()> {
+    let notification = |kind: NotificationKind, recipient: Uuid| {
+        Notification::new(kind, recipient, todo_id, after.task, issuer.clone())
+    };
+    let texts = [
+        (TextField::Name, &before.name, &after.name),
+        (
+            TextField::Description,
+            &before.description,
+            &after.description,
+        ),
+    ];
+    for (field, before, after) in texts {
+        notifier
+            .mentions(before, after, |recipient| {
+                notification(NotificationKind::Mention { field }, recipient)
+            })
+            .await?;
+    }
+    if after.assignee != before.assignee {
+        notifier
+            .send(notification(
+                NotificationKind::Assignment,
+                *after.assignee.id(),
+            ))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// What brings copies of the list up to date with its last change. Falls back to a
+/// snapshot if it couldn't be diffed.
+fn diff(differ: &mut TodoListDiffer, todo: &TodoList) -> TodoListUpdate {
+    match differ.update(todo) {
+        Ok(Some(update)) => update,
+        Ok(None) => TodoListUpdate::Snapshot,
+        Err(e) => {
+            tracing::error!("Failed to diff todo list: {e:?}");
+            TodoListUpdate::Snapshot
+        }
+    }
+}
+
+/// Starts or stops the issuer's timer on a task. Time entries are kept apart from the list,
+/// which is left untouched.
+async fn track_time(
+    todo_id: Uuid,
+    task_command: &TaskCommandMeta,
+    issuer: &User,
+    todo: &TodoListUpdater,
+    pool: Pool,
+) -> CommandReply {
+    let task = task_command.task;
+    let revision = {
+        let todo = todo.borrow();
+        if todo.task(task).is_none() {
+            return Err(CommandError::UnknownTask { task });
+        }
+        todo.revision()
+    };
+    let result = match task_command.command {
+        TaskCommand::StartTimer => {
+            TimeEntry::start_timer(issuer.clone(), todo_id, task, pool).await
+        }
+        _ => TimeEntry::stop_timer(issuer, todo_id, task, pool).await,
+    };
+    if let Err(e) = result {
+        tracing::error!("Failed to track time: {e:?}");
+        return Err(CommandError::Unavailable);
+    }
+
+    Ok(AppliedCommand {
+        revision,
+        transformed: false,
+        unblocked: vec![],
+        created: None,
+    })
+}
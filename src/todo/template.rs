@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use deadpool_redis::Pool;
+use redis::AsyncCommands;
+use redis_macros::{FromRedisValue, ToRedisArgs};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::user::User;
+
+use super::list::{CloneOptions, TodoList, TodoTask};
+
+/// A named snapshot of a list's tasks, saved in its owner's account
+/// and used to create new lists.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRedisValue, ToRedisArgs)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoTemplate {
+    name: String,
+    list_name: String,
+    tasks: Vec<TodoTask>,
+    created_at: DateTime<Utc>,
+}
+
+impl TodoTemplate {
+    pub fn from_list(name: String, list: &TodoList) -> Self {
+        Self {
+            name,
+            list_name: list.name().to_owned(),
+            tasks: list.tasks().to_vec(),
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Creates a fresh list out of this template. Every task starts undone
+    /// and assigned to `issuer`.
+    pub fn instantiate(&self, issuer: &User) -> TodoList {
+        let mut list = TodoList::new(self.tasks.clone());
+        list.rename(self.list_name.clone());
+        list.duplicate(
+            CloneOptions {
+                reset_done: true,
+                reset_assignees: true,
+            },
+            issuer,
+        )
+    }
+
+    pub async fn store(&self, owner: Uuid, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis
+            .hset::<_, _, _, ()>(templates_key(owner), &self.name, self)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn from_redis(owner: Uuid, name: &str, pool: Pool) -> anyhow::Result<Option<Self>> {
+        let mut redis = pool.get().await?;
+        let template = redis.hget(templates_key(owner), name).await?;
+        Ok(template)
+    }
+
+    pub async fn names(owner: Uuid, pool: Pool) -> anyhow::Result<Vec<String>> {
+        let mut redis = pool.get().await?;
+        let names = redis.hkeys(templates_key(owner)).await?;
+        Ok(names)
+    }
+
+    pub async fn delete(owner: Uuid, name: &str, pool: Pool) -> anyhow::Result<bool> {
+        let mut redis = pool.get().await?;
+        let removed: usize = redis.hdel(templates_key(owner), name).await?;
+        Ok(removed > 0)
+    }
+}
+
+fn templates_key(owner: Uuid) -> String {
+    format!("templates:{owner}")
+}
@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::CommandError;
+
+/// Number of edits kept per task to rebase concurrent edits on.
+const TEXT_LOG_CAPACITY: usize = 128;
+
+/// Text of a task that can be edited collaboratively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TextField {
+    Name,
+    Description,
+}
+
+/// One step of an edit, walking the text from its start.
+/// Lengths and positions are counted in Unicode scalar values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TextOperation {
+    /// Keeps the next characters.
+    Retain(usize),
+    Insert(String),
+    /// Removes the next characters.
+    Delete(usize),
+}
+
+/// Operations on a task's text, made by a client that had seen the list at `base_revision`.
+/// Characters past the last operation are kept.
+///
+/// Edits made concurrently by others are merged: the operations are rebased on every edit
+/// to the same text the server applied after `base_revision`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TextEdit {
+    pub field: TextField,
+    pub base_revision: u64,
+    pub ops: Vec<TextOperation>,
+}
+
+#[derive(Debug, Clone)]
+struct LoggedEdit {
+    field: TextField,
+    /// Revision of the list the edit has been applied on top of.
+    base_revision: u64,
+    ops: Vec<TextOperation>,
+}
+
+/// Recent edits to a task's texts, never stored.
+#[derive(Debug, Clone, Default)]
+pub struct TextLog {
+    edits: VecDeque<LoggedEdit>,
+    /// Every edit applied on top of this revision or a later one is in `edits`.
+    /// Unknown until the first edit, as the log starts empty whenever the list is loaded.
+    complete_since: Option<u64>,
+}
+
+impl TextLog {
+    /// Rebases `edit` on the edits applied since its base revision, and applies it to `text`.
+    /// `task_revision` is the revision at which the task last changed, and `revision` the
+    /// list's current one.
+    pub fn apply(
+        &mut self,
+        text: &mut String,
+        edit: TextEdit,
+        task_revision: u64,
+        revision: u64,
+    ) -> Result<(), CommandError> {
+        let complete_since = *self.complete_since.get_or_insert(task_revision);
+        if edit.base_revision < complete_since {
+            return Err(CommandError::Conflict {
+                expected_revision: edit.base_revision,
+                revision: complete_since,
+            });
+        }
+        let ops = self
+            .edits
+            .iter()
+            .filter(|logged| {
+                logged.field == edit.field && logged.base_revision >= edit.base_revision
+            })
+            .fold(edit.ops, |ops, logged| transform(ops, &logged.ops));
+        *text = apply(text, &ops)?;
+        self.record(edit.field, revision, ops);
+
+        Ok(())
+    }
+
+    /// Records a change to the whole text, so that concurrent edits are rebased on it.
+    pub fn replace(
+        &mut self,
+        field: TextField,
+        old: &str,
+        new: &str,
+        task_revision: u64,
+        revision: u64,
+    ) {
+        self.complete_since.get_or_insert(task_revision);
+        let ops = vec![
+            TextOperation::Delete(old.chars().count()),
+            TextOperation::Insert(new.to_owned()),
+        ];
+        self.record(field, revision, ops);
+    }
+
+    fn record(&mut self, field: TextField, base_revision: u64, ops: Vec<TextOperation>) {
+        self.edits.push_back(LoggedEdit {
+            field,
+            base_revision,
+            ops,
+        });
+        if self.edits.len() > TEXT_LOG_CAPACITY {
+            if let Some(dropped) = self.edits.pop_front() {
+                self.complete_since = Some(dropped.base_revision + 1);
+            }
+        }
+    }
+}
+
+fn malformed(message: &str) -> CommandError {
+    CommandError::Malformed {
+        message: message.to_owned(),
+    }
+}
+
+/// Applies `ops` to `text`, returning the edited text.
+pub fn apply(text: &str, ops: &[TextOperation]) -> Result<String, CommandError> {
+    let mut chars = text.chars();
+    let mut edited = String::with_capacity(text.len());
+    for op in ops {
+        match op {
+            TextOperation::Retain(count) => {
+                let before = edited.len();
+                edited.extend(chars.by_ref().take(*count));
+                if edited[before..].chars().count() < *count {
+                    return Err(malformed("Edit retains past the end of the text"));
+                }
+            }
+            TextOperation::Insert(inserted) => edited.push_str(inserted),
+            TextOperation::Delete(count) => {
+                if chars.by_ref().take(*count).count() < *count {
+                    return Err(malformed("Edit deletes past the end of the text"));
+                }
+            }
+        }
+    }
+    edited.extend(chars);
+
+    Ok(edited)
+}
+
+/// Rewrites `ops` so that they apply on top of `applied`, both having been made on the same text.
+/// When both insert at the same position, the already applied insertion comes first.
+pub fn transform(ops: Vec<TextOperation>, applied: &[TextOperation]) -> Vec<TextOperation> {
+    let mut transformed = Vec::with_capacity(ops.len());
+    let mut ops = ops.into_iter();
+    let mut applied = applied.iter().cloned();
+    let mut op = ops.next();
+    let mut other = applied.next();
+    loop {
+        match (op.take(), other.take()) {
+            (None, None) => break,
+            (current, Some(TextOperation::Insert(inserted))) => {
+                transformed.push(TextOperation::Retain(inserted.chars().count()));
+                op = current;
+                other = applied.next();
+            }
+            (Some(TextOperation::Insert(inserted)), remaining) => {
+                transformed.push(TextOperation::Insert(inserted));
+                op = ops.next();
+                other = remaining;
+            }
+            // Past the end of either side, the rest of the text is implicitly retained
+            (None, Some(_)) => break,
+            (Some(current), None) => {
+                transformed.push(current);
+                transformed.extend(ops.by_ref());
+                break;
+            }
+            (Some(current), Some(remaining)) => {
+                let (len, other_len) = (span(&current), span(&remaining));
+                let step = len.min(other_len);
+                match (&current, &remaining) {
+                    (TextOperation::Retain(_), TextOperation::Retain(_)) => {
+                        transformed.push(TextOperation::Retain(step))
+                    }
+                    (TextOperation::Delete(_), TextOperation::Retain(_)) => {
+                        transformed.push(TextOperation::Delete(step))
+                    }
+                    // Already removed by the applied edit
+                    (_, TextOperation::Delete(_)) => (),
+                    (TextOperation::Insert(_), _) | (_, TextOperation::Insert(_)) => {
+                        unreachable!("Insertions are handled above")
+                    }
+                }
+                op = shorten(current, step).or_else(|| ops.next());
+                other = shorten(remaining, step).or_else(|| applied.next());
+            }
+        }
+    }
+    transformed
+}
+
+/// Number of characters of the original text an operation walks over.
+fn span(op: &TextOperation) -> usize {
+    match op {
+        TextOperation::Retain(count) | TextOperation::Delete(count) => *count,
+        TextOperation::Insert(_) => 0,
+    }
+}
+
+/// What is left of a retain or delete after walking `step` characters.
+fn shorten(op: TextOperation, step: usize) -> Option<TextOperation> {
+    match op {
+        TextOperation::Retain(count) if count > step => Some(TextOperation::Retain(count - step)),
+        TextOperation::Delete(count) if count > step => Some(TextOperation::Delete(count - step)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, transform, TextOperation::*};
+
+    #[test]
+    fn concurrent_edits_converge() {
+        let text = "buy milk";
+        let cases = [
+            (
+                vec![Retain(4), Insert("oat ".to_owned())],
+                vec![Insert("To ".to_owned())],
+            ),
+            (
+                vec![Retain(4), Delete(4)],
+                vec![Retain(6), Insert("x".to_owned())],
+            ),
+            (vec![Delete(3)], vec![Retain(1), Delete(4)]),
+        ];
+        for (first, second) in cases {
+            let first_then_second = apply(
+                &apply(text, &first).unwrap(),
+                &transform(second.clone(), &first),
+            )
+            .unwrap();
+            let second_then_first = apply(
+                &apply(text, &second).unwrap(),
+                &transform(first.clone(), &second),
+            )
+            .unwrap();
+            assert_eq!(
+                first_then_second, second_then_first,
+                "{first:?} / {second:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn applied_insertions_come_first() {
+        let applied = vec![Retain(8), Insert("!".to_owned())];
+        let ops = transform(vec![Retain(8), Insert("?".to_owned())], &applied);
+        let text = apply(&apply("buy milk", &applied).unwrap(), &ops).unwrap();
+        assert_eq!(text, "buy milk!?");
+    }
+
+    #[test]
+    fn edits_past_the_end_are_rejected() {
+        assert!(apply("abc", &[Retain(4)]).is_err());
+        assert!(apply("abc", &[Retain(1), Delete(3)]).is_err());
+        assert_eq!(apply("abc", &[Retain(1)]).unwrap(), "abc");
+    }
+}
@@ -0,0 +1,304 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use deadpool_redis::Pool;
+use redis::AsyncCommands;
+use redis_macros::{FromRedisValue, ToRedisArgs};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+use uuid::Uuid;
+
+use crate::user::User;
+
+use super::list::TodoList;
+
+/// A span of time a user spent working on a task.
+/// Entries without an end are running timers.
+///
+/// Time entries live outside of the list document: a user can only have one running
+/// timer across all lists, so starting a timer must be able to stop one in a list
+/// that isn't loaded.
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, FromRedisValue, ToRedisArgs,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeEntry {
+    id: Uuid,
+    user: User,
+    list: Uuid,
+    task: Uuid,
+    started_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+}
+
+impl TimeEntry {
+    fn start(user: User, list: Uuid, task: Uuid) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user,
+            list,
+            task,
+            started_at: Utc::now(),
+            ended_at: None,
+        }
+    }
+
+    pub const fn user(&self) -> &User {
+        &self.user
+    }
+
+    pub const fn list(&self) -> Uuid {
+        self.list
+    }
+
+    pub const fn task(&self) -> Uuid {
+        self.task
+    }
+
+    pub const fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub const fn is_running(&self) -> bool {
+        self.ended_at.is_none()
+    }
+
+    /// Time tracked by this entry. Running entries are measured up to now.
+    pub fn duration(&self) -> Duration {
+        (self.ended_at.unwrap_or_else(Utc::now) - self.started_at)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Starts a timer for `user` on the given task, stopping their running timer if any.
+    /// Returns the stopped entry.
+    pub async fn start_timer(
+        user: User,
+        list: Uuid,
+        task: Uuid,
+        pool: Pool,
+    ) -> anyhow::Result<Option<Self>> {
+        let mut redis = pool.get().await?;
+        loop {
+            let running = Self::watch_running(*user.id(), &mut redis).await?;
+            if running
+                .as_ref()
+                .is_some_and(|entry| entry.list == list && entry.task == task)
+            {
+                redis::cmd("UNWATCH")
+                    .query_async::<_, ()>(&mut redis)
+                    .await?;
+                return Ok(None);
+            }
+
+            let entry = Self::start(user.clone(), list, task);
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            let previous = running.map(|previous| previous.finish(&mut pipe));
+            pipe.set(running_timer_key(*user.id()), &entry)
+                .ignore()
+                .hset(running_timers_key(list), user.id().to_string(), &entry)
+                .ignore();
+            // Aborted if the running timer has been changed since being watched
+            let swapped: Option<()> = pipe.query_async(&mut redis).await?;
+            if swapped.is_some() {
+                return Ok(previous);
+            }
+        }
+    }
+
+    /// Stops the running timer of `user` if it's tracking the given task.
+    pub async fn stop_timer(
+        user: &User,
+        list: Uuid,
+        task: Uuid,
+        pool: Pool,
+    ) -> anyhow::Result<Option<Self>> {
+        let mut redis = pool.get().await?;
+        loop {
+            let entry = match Self::watch_running(*user.id(), &mut redis).await? {
+                Some(entry) if entry.list == list && entry.task == task => entry,
+                _ => {
+                    redis::cmd("UNWATCH")
+                        .query_async::<_, ()>(&mut redis)
+                        .await?;
+                    return Ok(None);
+                }
+            };
+
+            let mut pipe = redis::pipe();
+            pipe.atomic().del(running_timer_key(*user.id())).ignore();
+            let entry = entry.finish(&mut pipe);
+            // Aborted if the running timer has been changed since being watched
+            let stopped: Option<()> = pipe.query_async(&mut redis).await?;
+            if stopped.is_some() {
+                return Ok(Some(entry));
+            }
+        }
+    }
+
+    /// Watches the running timer of `user` until the next transaction, and returns it.
+    async fn watch_running(
+        user: Uuid,
+        redis: &mut deadpool_redis::Connection,
+    ) -> anyhow::Result<Option<Self>> {
+        redis::cmd("WATCH")
+            .arg(running_timer_key(user))
+            .query_async::<_, ()>(redis)
+            .await?;
+        let running = redis.get(running_timer_key(user)).await;
+        if running.is_err() {
+            redis::cmd("UNWATCH").query_async::<_, ()>(redis).await?;
+        }
+        Ok(running?)
+    }
+
+    pub async fn running_timer(user: Uuid, pool: Pool) -> anyhow::Result<Option<Self>> {
+        let mut redis = pool.get().await?;
+        let running = redis.get(running_timer_key(user)).await?;
+        Ok(running)
+    }
+
+    /// Every entry logged on a list, running timers included.
+    pub async fn list_entries(list: Uuid, pool: Pool) -> anyhow::Result<Vec<Self>> {
+        let mut redis = pool.get().await?;
+        let mut entries: Vec<Self> = redis.lrange(list_entries_key(list), 0, -1).await?;
+        let running: Vec<Self> = redis.hvals(running_timers_key(list)).await?;
+        entries.extend(running);
+        Ok(entries)
+    }
+
+    /// Entries logged by `user` that started within `from..=to`.
+    pub async fn user_entries(
+        user: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        pool: Pool,
+    ) -> anyhow::Result<Vec<Self>> {
+        let mut redis = pool.get().await?;
+        let mut entries: Vec<Self> = redis
+            .zrangebyscore(
+                user_entries_key(user),
+                from.timestamp_millis(),
+                to.timestamp_millis(),
+            )
+            .await?;
+        let running: Option<Self> = redis.get(running_timer_key(user)).await?;
+        entries.extend(running.filter(|entry| (from..=to).contains(&entry.started_at)));
+        Ok(entries)
+    }
+
+    /// Ends the entry, queuing its move from the running timers to the log.
+    fn finish(mut self, pipe: &mut redis::Pipeline) -> Self {
+        self.ended_at = Some(Utc::now());
+        pipe.hdel(running_timers_key(self.list), self.user.id().to_string())
+            .ignore()
+            .rpush(list_entries_key(self.list), &self)
+            .ignore()
+            .zadd(
+                user_entries_key(*self.user.id()),
+                &self,
+                self.started_at.timestamp_millis(),
+            )
+            .ignore();
+        self
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskTimeReport {
+    pub task: Uuid,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[schemars(with = "u64")]
+    pub tracked: Duration,
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[schemars(with = "Option<u64>")]
+    pub estimate: Option<Duration>,
+    pub running: Vec<User>,
+}
+
+/// Time tracked on a list, in total and per task.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListTimeReport {
+    pub list: Uuid,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[schemars(with = "u64")]
+    pub tracked: Duration,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[schemars(with = "u64")]
+    pub estimate: Duration,
+    pub tasks: Vec<TaskTimeReport>,
+}
+
+impl ListTimeReport {
+    pub fn new(list: &TodoList, entries: &[TimeEntry]) -> Self {
+        let tasks = list
+            .tasks()
+            .iter()
+            .map(|task| {
+                let task_entries = entries.iter().filter(|entry| entry.task == task.id());
+                TaskTimeReport {
+                    task: task.id(),
+                    tracked: task_entries.clone().map(TimeEntry::duration).sum(),
+                    estimate: task.estimate(),
+                    running: task_entries
+                        .filter(|entry| entry.is_running())
+                        .map(|entry| entry.user.clone())
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            list: list.id(),
+            tracked: tasks.iter().map(|task| task.tracked).sum(),
+            estimate: tasks.iter().filter_map(|task| task.estimate).sum(),
+            tasks,
+        }
+    }
+}
+
+/// Time a user tracked across all lists in a date range.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserTimeReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[schemars(with = "u64")]
+    pub tracked: Duration,
+    pub entries: Vec<TimeEntry>,
+}
+
+impl UserTimeReport {
+    pub fn new(from: DateTime<Utc>, to: DateTime<Utc>, entries: Vec<TimeEntry>) -> Self {
+        Self {
+            from,
+            to,
+            tracked: entries.iter().map(TimeEntry::duration).sum(),
+            entries,
+        }
+    }
+}
+
+fn running_timer_key(user: Uuid) -> String {
+    format!("timer:{user}")
+}
+
+fn running_timers_key(list: Uuid) -> String {
+    format!("running_timers:{list}")
+}
+
+fn list_entries_key(list: Uuid) -> String {
+    format!("time_entries:{list}")
+}
+
+fn user_entries_key(user: Uuid) -> String {
+    format!("user_time_entries:{user}")
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use deadpool_redis::Pool;
+use redis::AsyncCommands;
+use redis_macros::{FromRedisValue, ToRedisArgs};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// When a user last saw a list, and what it was like then.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    FromRedisValue,
+    ToRedisArgs,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ListVisit {
+    pub revision: u64,
+    pub visited_at: DateTime<Utc>,
+}
+
+impl ListVisit {
+    pub fn now(revision: u64) -> Self {
+        Self {
+            revision,
+            visited_at: Utc::now(),
+        }
+    }
+
+    pub async fn record(&self, user: Uuid, list: Uuid, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis
+            .hset::<_, _, _, ()>(visits_key(user), list.to_string(), self)
+            .await?;
+        Ok(())
+    }
+
+    /// Last visit of `user` to each list they visited.
+    pub async fn of_user(user: Uuid, pool: Pool) -> anyhow::Result<HashMap<Uuid, Self>> {
+        let mut redis = pool.get().await?;
+        let visits: HashMap<String, Self> = redis.hgetall(visits_key(user)).await?;
+        Ok(visits
+            .into_iter()
+            .filter_map(|(list, visit)| Some((list.parse().ok()?, visit)))
+            .collect())
+    }
+}
+
+fn visits_key(user: Uuid) -> String {
+    format!("visits:{user}")
+}
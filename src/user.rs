@@ -1,12 +1,19 @@
 use std::fmt::Display;
 
+use deadpool_redis::Pool;
 use petname::Petnames;
 use rand::{rngs::StdRng, SeedableRng};
+use redis::AsyncCommands;
 use redis_macros::{FromRedisValue, ToRedisArgs};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{Mutex, OnceCell};
 use uuid::Uuid;
 
+const USER_HANDLES_KEY: &str = "user_handles";
+/// Handles generated for a new user before giving up on finding one nobody has.
+const HANDLE_ATTEMPTS: usize = 10;
+
 static USER_HANDLE_GEN: OnceCell<Mutex<UserHandleGenerator>> = OnceCell::const_new();
 
 pub struct UserHandleGenerator {
@@ -41,7 +48,7 @@ impl Default for UserHandleGenerator {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Hash)]
 pub struct UserHandle(String);
 
 impl AsRef<str> for UserHandle {
@@ -67,7 +74,16 @@ impl UserHandle {
 }
 
 #[derive(
-    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash, FromRedisValue, ToRedisArgs,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Hash,
+    FromRedisValue,
+    ToRedisArgs,
 )]
 pub struct User {
     id: Uuid,
@@ -89,6 +105,39 @@ impl User {
     pub const fn handle(&self) -> &UserHandle {
         &self.handle
     }
+
+    /// Creates a user with a handle no indexed user has. Users are only indexed once they
+    /// take part in a list, see [`Self::index`], so that sessions nobody uses don't pile up
+    /// in the index.
+    pub async fn create(pool: Pool) -> anyhow::Result<Self> {
+        let mut redis = pool.get().await?;
+        for _ in 0..HANDLE_ATTEMPTS {
+            let user = Self::new().await;
+            let taken: bool = redis
+                .hexists(USER_HANDLES_KEY, user.handle.as_ref())
+                .await?;
+            if !taken {
+                return Ok(user);
+            }
+            tracing::debug!("Handle {} is taken", user.handle.as_ref());
+        }
+        anyhow::bail!("No free handle found in {HANDLE_ATTEMPTS} attempts")
+    }
+
+    /// Makes the user findable by handle, e.g. to be mentioned. Handles are kept by the
+    /// first user indexed with them.
+    pub async fn index(&self, pool: Pool) -> anyhow::Result<()> {
+        let mut redis = pool.get().await?;
+        redis
+            .hset_nx::<_, _, _, ()>(USER_HANDLES_KEY, self.handle.as_ref(), self)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn from_handle(handle: &str, pool: Pool) -> anyhow::Result<Option<Self>> {
+        let mut redis = pool.get().await?;
+        Ok(redis.hget(USER_HANDLES_KEY, handle).await?)
+    }
 }
 
 impl Display for User {
@@ -0,0 +1,130 @@
+use anyhow::Context;
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::{stream::SplitSink, SinkExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Encoding of the messages exchanged over a WebSocket,
+/// negotiated through the `Sec-WebSocket-Protocol` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl WireFormat {
+    /// Subprotocols accepted by the server, in order of preference.
+    pub const PROTOCOLS: [&'static str; 3] = ["coodo.v1.json", "coodo.v1.msgpack", "coodo.v1.cbor"];
+
+    pub fn from_protocol(protocol: &str) -> Option<Self> {
+        match protocol {
+            "coodo.v1.json" => Some(Self::Json),
+            "coodo.v1.msgpack" => Some(Self::MessagePack),
+            "coodo.v1.cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// JSON is sent as text frames, binary formats as binary frames.
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> anyhow::Result<Message> {
+        let message = match self {
+            Self::Json => Message::Text(serde_json::to_string(value)?),
+            Self::MessagePack => {
+                let mut data = vec![];
+                // Human readable, so that ids and dates are encoded as in JSON
+                let mut serializer = rmp_serde::Serializer::new(&mut data)
+                    .with_struct_map()
+                    .with_human_readable();
+                value.serialize(&mut serializer)?;
+                Message::Binary(data)
+            }
+            Self::Cbor => {
+                let mut data = vec![];
+                ciborium::into_writer(value, &mut data)?;
+                Message::Binary(data)
+            }
+        };
+
+        Ok(message)
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, data: &[u8]) -> anyhow::Result<T> {
+        let value = match self {
+            Self::Json => serde_json::from_slice(data)?,
+            Self::MessagePack => {
+                let mut deserializer = rmp_serde::Deserializer::new(data).with_human_readable();
+                T::deserialize(&mut deserializer)?
+            }
+            Self::Cbor => ciborium::from_reader(data)?,
+        };
+
+        Ok(value)
+    }
+}
+
+/// Sending half of a WebSocket, encoding messages in the negotiated [`WireFormat`].
+#[derive(Debug)]
+pub struct WireSink {
+    sink: SplitSink<WebSocket, Message>,
+    format: WireFormat,
+}
+
+impl WireSink {
+    pub fn new(sink: SplitSink<WebSocket, Message>, format: WireFormat) -> Self {
+        Self { sink, format }
+    }
+
+    pub const fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    pub async fn send<T: Serialize + ?Sized>(&mut self, value: &T) -> anyhow::Result<()> {
+        let message = self
+            .format
+            .encode(value)
+            .context("Failed to serialize message")?;
+        self.send_raw(message).await
+    }
+
+    /// Sends an already encoded message.
+    pub async fn send_raw(&mut self, message: Message) -> anyhow::Result<()> {
+        self.sink
+            .send(message)
+            .await
+            .context("Failed to send ws message")
+    }
+
+    pub async fn close(&mut self) -> anyhow::Result<()> {
+        self.sink.close().await.context("Failed to close ws")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+
+    use crate::todo::{Command, CommandRequest};
+
+    use super::WireFormat;
+
+    #[test]
+    fn command_requests_round_trip_in_every_format() {
+        let request = CommandRequest {
+            id: Some("req-1".to_owned()),
+            expected_revision: Some(3),
+            command: Command::SetListName("groceries".to_owned()),
+        };
+        for format in [WireFormat::Json, WireFormat::MessagePack, WireFormat::Cbor] {
+            let data = match format.encode(&request).unwrap() {
+                Message::Text(text) => text.into_bytes(),
+                Message::Binary(data) => data,
+                _ => unreachable!(),
+            };
+            let decoded = format.decode::<CommandRequest>(&data).unwrap();
+            assert_eq!(decoded.id.as_deref(), Some("req-1"));
+            assert_eq!(decoded.expected_revision, Some(3));
+            assert!(matches!(decoded.command, Command::SetListName(name) if name == "groceries"));
+        }
+    }
+}
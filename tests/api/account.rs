@@ -0,0 +1,157 @@
+use std::{net::IpAddr, time::Duration};
+
+use coodo_be::account::AccountInfo;
+use reqwest::{Client, StatusCode};
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn registered_users_can_log_in_from_another_device() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let username = format!("user_{}", &Uuid::new_v4().simple().to_string()[..12]);
+    let credentials = serde_json::json!({ "username": username, "password": "correct horse" });
+    let account_url = format!("{}/account", app.address);
+
+    let response = client
+        .post(&account_url)
+        .json(&serde_json::json!({ "username": "x", "password": "short" }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = client.post(&account_url).json(&credentials).send().await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let account = response.json::<AccountInfo>().await?;
+    assert_eq!(account.user, user);
+
+    let mut other_client = Client::builder().cookie_store(true).build()?;
+    let anonymous = app.get_user(&mut other_client).await?;
+    let other_list_id = app.create_todo_list(&mut other_client).await?;
+    let response = other_client
+        .post(&account_url)
+        .json(&credentials)
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = other_client
+        .post(format!("{account_url}/login"))
+        .json(&serde_json::json!({ "username": username, "password": "wrong horse" }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    other_client
+        .post(format!("{account_url}/login"))
+        .json(&serde_json::json!({ "username": username.to_uppercase(), "password": "correct horse" }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    assert_eq!(app.get_user(&mut other_client).await?, user);
+    assert_ne!(anonymous, user);
+    let lists = app.get_joined_todo_lists(&other_client).await?;
+    let ids = lists
+        .iter()
+        .map(|list| list.info().id())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![todo_list_id, other_list_id]);
+    let lists = app.get_joined_todo_lists(&client).await?;
+    assert_eq!(
+        lists.len(),
+        2,
+        "Lists are shared across the account's sessions"
+    );
+
+    let response = client.post(format!("{account_url}/logout")).send().await?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = client.get(&account_url).send().await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_ne!(app.get_user(&mut client).await?, user);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lists_joined_at_once_from_several_devices_are_kept() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let username = format!("user_{}", &Uuid::new_v4().simple().to_string()[..12]);
+    let credentials = serde_json::json!({ "username": username, "password": "correct horse" });
+    let mut clients = Vec::new();
+    for device in 0..4 {
+        let mut client = Client::builder().cookie_store(true).build()?;
+        app.get_user(&mut client).await?;
+        let path = if device == 0 { "" } else { "/login" };
+        client
+            .post(format!("{}/account{path}", app.address))
+            .json(&credentials)
+            .send()
+            .await?
+            .error_for_status()?;
+        clients.push(client);
+    }
+
+    let created = futures_util::future::try_join_all(
+        clients
+            .iter_mut()
+            .map(|client| app.create_todo_list(client)),
+    )
+    .await?;
+
+    let lists = app.get_joined_todo_lists(&clients[0]).await?;
+    for id in created {
+        assert!(lists.iter().any(|list| list.info().id() == id));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_logins_are_rate_limited() -> anyhow::Result<()> {
+    let app = TestApp::spawn_with(|settings| {
+        settings.todo_handler.login_attempts = 2;
+        settings.todo_handler.login_attempt_window = Duration::from_secs(60);
+    })
+    .await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+    app.get_user(&mut client).await?;
+    let username = format!("user_{}", &Uuid::new_v4().simple().to_string()[..12]);
+    let login_url = format!("{}/account/login", app.address);
+    client
+        .post(format!("{}/account", app.address))
+        .json(&serde_json::json!({ "username": username, "password": "correct horse" }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    for _ in 0..2 {
+        let response = client
+            .post(&login_url)
+            .json(&serde_json::json!({ "username": username, "password": "wrong horse" }))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    let response = client
+        .post(&login_url)
+        .json(&serde_json::json!({ "username": username, "password": "correct horse" }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Failed attempts from elsewhere don't lock the account's owner out
+    let mut owner = Client::builder()
+        .cookie_store(true)
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()?;
+    app.get_user(&mut owner).await?;
+    let response = owner
+        .post(&login_url)
+        .json(&serde_json::json!({ "username": username, "password": "correct horse" }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use coodo_be::todo::ChatPage;
+use futures_util::{stream::SplitStream, SinkExt};
+use reqwest::{cookie::Jar, Client};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::helpers::{receive_message_of_type, receive_todo_list, TestApp, WsStream};
+
+async fn receive_chat(ws_stream: &mut SplitStream<WsStream>) -> anyhow::Result<serde_json::Value> {
+    Ok(receive_message_of_type(ws_stream, "chat").await?["data"].take())
+}
+
+fn chat(command: &str, data: serde_json::Value) -> Message {
+    Message::Text(serde_json::json!({ "type": command, "data": data, "id": command }).to_string())
+}
+
+#[tokio::test]
+async fn chat_messages_are_broadcast_and_kept() -> anyhow::Result<()> {
+    let app = TestApp::spawn_with(|settings| {
+        settings.todo_handler.chat_history_size = 3;
+        settings.todo_handler.chat_blocked_words = vec!["Spam".to_owned()];
+    })
+    .await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    let other_jar = Arc::new(Jar::default());
+    let mut other_client = Client::builder()
+        .cookie_provider(other_jar.clone())
+        .build()?;
+    let _other_user = app.get_user(&mut other_client).await?;
+    let (mut other_sink, mut other_stream) =
+        app.connect_to_todo_list(todo_list_id, &other_jar).await?;
+    receive_todo_list(&mut other_stream).await?;
+
+    ws_sink
+        .send(chat("send_chat", serde_json::json!({ "text": " Milk? " })))
+        .await?;
+    let sent = receive_chat(&mut other_stream).await?;
+    assert_eq!(sent["event"], "sent");
+    assert_eq!(sent["message"]["text"], "Milk?");
+    assert_eq!(sent["message"]["author"], serde_json::to_value(&user)?);
+    assert_eq!(receive_chat(&mut ws_stream).await?, sent);
+    let message = sent["message"]["id"].clone();
+
+    // Only authors can change their messages
+    other_sink
+        .send(chat(
+            "edit_chat",
+            serde_json::json!({ "message": message, "text": "Beer!" }),
+        ))
+        .await?;
+    let error = receive_message_of_type(&mut other_stream, "error").await?;
+    assert_eq!(error["data"]["id"], "edit_chat");
+    assert_eq!(error["data"]["reason"], "not_author");
+    ws_sink
+        .send(chat(
+            "edit_chat",
+            serde_json::json!({ "message": message, "text": "Oat milk?" }),
+        ))
+        .await?;
+    let edited = receive_chat(&mut other_stream).await?;
+    assert_eq!(edited["event"], "edited");
+    assert_eq!(edited["message"]["text"], "Oat milk?");
+    assert!(edited["message"]["edited_at"].is_string());
+
+    ws_sink
+        .send(chat("send_chat", serde_json::json!({ "text": "Buy SPAM" })))
+        .await?;
+    let error = receive_message_of_type(&mut ws_stream, "error").await?;
+    assert_eq!(error["data"]["reason"], "moderated");
+
+    for text in ["Eggs", "Flour", "Sugar"] {
+        other_sink
+            .send(chat("send_chat", serde_json::json!({ "text": text })))
+            .await?;
+        receive_chat(&mut other_stream).await?;
+    }
+
+    // Only the latest messages are kept
+    let chat_url = format!("{}/todos/{}/chat", app.address, todo_list_id);
+    let page = client
+        .get(format!("{chat_url}?limit=2"))
+        .send()
+        .await?
+        .json::<ChatPage>()
+        .await?;
+    let texts = |page: &ChatPage| {
+        page.messages
+            .iter()
+            .map(|message| message.text().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(texts(&page), ["Flour", "Sugar"]);
+    assert!(page.has_more);
+    let flour = &page.messages[0];
+
+    other_sink
+        .send(chat(
+            "delete_chat",
+            serde_json::json!({ "message": flour.id() }),
+        ))
+        .await?;
+    let deleted = loop {
+        let event = receive_chat(&mut ws_stream).await?;
+        if event["event"] == "deleted" {
+            break event;
+        }
+    };
+    assert_eq!(deleted["message"], flour.id().to_string());
+
+    // Paging on from a deleted message still reaches older ones
+    let older = client
+        .get(&chat_url)
+        .query(&[("before", flour.sent_at().to_rfc3339())])
+        .send()
+        .await?
+        .json::<ChatPage>()
+        .await?;
+    assert_eq!(texts(&older), ["Eggs"]);
+    assert!(!older.has_more);
+    let page = client
+        .get(&chat_url)
+        .send()
+        .await?
+        .json::<ChatPage>()
+        .await?;
+    assert_eq!(texts(&page), ["Eggs", "Sugar"]);
+
+    Ok(())
+}
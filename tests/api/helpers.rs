@@ -1,20 +1,29 @@
-use std::net::TcpListener;
+use std::{net::TcpListener, time::Duration};
 
 use anyhow::Context;
-use coodo_be::{startup::get_redis_pool, telemetry, todo::TodoListInfo, user::User};
+use coodo_be::{
+    message::{ServerMessage, PROTOCOL_VERSION},
+    session::JoinedTodoList,
+    settings::Settings,
+    startup::get_redis_pool,
+    telemetry,
+    todo::{Command, TodoList},
+    user::User,
+};
 use deadpool_redis::Pool;
 use futures_util::{
     stream::{SplitSink, SplitStream},
-    StreamExt,
+    SinkExt, StreamExt,
 };
 use once_cell::sync::Lazy;
 use rand::Rng;
 use reqwest::{cookie::Jar, Client};
-use tokio::{net::TcpStream, task::JoinHandle};
+use serde::de::DeserializeOwned;
+use tokio::{net::TcpStream, task::JoinHandle, time::timeout};
 use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 use uuid::Uuid;
 
-type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     if let Ok(level) = std::env::var("TEST_LOG") {
@@ -34,9 +43,17 @@ pub struct TestApp {
 impl TestApp {
     #[tracing::instrument(skip_all)]
     pub async fn spawn() -> Self {
+        Self::spawn_with(|_| ()).await
+    }
+
+    /// Spawns the app after letting `configure` tweak its settings.
+    pub async fn spawn_with(configure: impl FnOnce(&mut Settings)) -> Self {
         Lazy::force(&TRACING);
 
-        let settings = coodo_be::settings::get_settings().expect("Failed to read config file");
+        let mut settings = coodo_be::settings::get_settings().expect("Failed to read config file");
+        // Keep lists from lingering after tests disconnect
+        settings.todo_handler.presence_grace_period = Duration::from_millis(200);
+        configure(&mut settings);
         let mut redis_settings = settings.redis;
         redis_settings.db = rand::thread_rng().gen_range(1..15);
         let pool = get_redis_pool(redis_settings);
@@ -89,40 +106,205 @@ impl TestApp {
         todo_id: Uuid,
         cookie_jar: &Jar,
     ) -> anyhow::Result<(SplitSink<WsStream, Message>, SplitStream<WsStream>)> {
+        self.connect_to_todo_list_with(todo_id, "", cookie_jar)
+            .await
+    }
+
+    pub async fn connect_to_todo_list_with(
+        &self,
+        todo_id: Uuid,
+        query: &str,
+        cookie_jar: &Jar,
+    ) -> anyhow::Result<(SplitSink<WsStream, Message>, SplitStream<WsStream>)> {
+        let ws_request = self
+            .ws_request(todo_id, query, cookie_jar)?
+            .body(())
+            .context("Failed to build ws connection request")?;
+        let (ws_sink, mut ws_stream) = connect(ws_request).await?;
+        let hello = receive_message::<ServerMessage>(&mut ws_stream).await?;
+        anyhow::ensure!(
+            matches!(hello, ServerMessage::Hello { version, .. } if version == PROTOCOL_VERSION),
+            "Expected a handshake, got {hello:?}"
+        );
+        Ok((ws_sink, ws_stream))
+    }
+
+    /// Connects asking for the given `Sec-WebSocket-Protocol`, leaving the handshake unread.
+    pub async fn connect_to_todo_list_using(
+        &self,
+        todo_id: Uuid,
+        protocol: &str,
+        cookie_jar: &Jar,
+    ) -> anyhow::Result<(SplitSink<WsStream, Message>, SplitStream<WsStream>)> {
+        let ws_request = self
+            .ws_request(todo_id, "", cookie_jar)?
+            .header("Sec-WebSocket-Protocol", protocol)
+            .body(())
+            .context("Failed to build ws connection request")?;
+        connect(ws_request).await
+    }
+
+    fn ws_request(
+        &self,
+        todo_id: Uuid,
+        query: &str,
+        cookie_jar: &Jar,
+    ) -> anyhow::Result<hyper::http::request::Builder> {
         let sid = get_sid(cookie_jar)?;
-        let ws_request = hyper::http::Request::builder()
+        Ok(hyper::http::Request::builder()
             .method("GET")
-            .uri(format!("ws://127.0.0.1:{}/todos/{}", self.port, todo_id))
+            .uri(format!(
+                "ws://127.0.0.1:{}/todos/{}?{}",
+                self.port, todo_id, query
+            ))
             .header("Host", "localhost")
             .header("Upgrade", "websocket")
             .header("Connection", "Upgrade")
             .header("Sec-WebSocket-Key", "x3JJHMbDL1EzLkh9GBhXDw==")
             .header("Sec-WebSocket-Version", "13")
-            .header("Cookie", format!("sid={}", sid))
-            .body(())
-            .context("Failed to build ws connection request")?;
-
-        let (ws_stream, _) = connect_async(ws_request)
-            .await
-            .context("Failed to establish ws connection")?;
-        Ok(ws_stream.split())
+            .header("Cookie", format!("sid={}", sid)))
     }
 
     pub async fn get_joined_todo_lists(
         &self,
         client: &Client,
-    ) -> anyhow::Result<Vec<TodoListInfo>> {
+    ) -> anyhow::Result<Vec<JoinedTodoList>> {
         client
             .get(format!("{}/todos", self.address))
             .send()
             .await
             .context("Failed to send GET /todos")?
-            .json::<Vec<TodoListInfo>>()
+            .json::<Vec<JoinedTodoList>>()
             .await
             .context("Failed to parse response")
     }
 }
 
+pub async fn send_command(
+    ws_sink: &mut SplitSink<WsStream, Message>,
+    command: &Command,
+) -> anyhow::Result<()> {
+    ws_sink
+        .send(Message::Binary(serde_json::to_vec(command)?))
+        .await
+        .context("Failed to send command")
+}
+
+pub async fn receive_todo_list(ws_stream: &mut SplitStream<WsStream>) -> anyhow::Result<TodoList> {
+    let message = receive_message::<ServerMessage>(ws_stream)
+        .await
+        .context("Failed to deserialize todo list")?;
+    match message {
+        ServerMessage::Snapshot(todo_list) => Ok(todo_list.into_owned()),
+        other => anyhow::bail!("Expected a snapshot, got {other:?}"),
+    }
+}
+
+pub async fn receive_message<T: DeserializeOwned>(
+    ws_stream: &mut SplitStream<WsStream>,
+) -> anyhow::Result<T> {
+    let msg = timeout(Duration::from_secs(1), ws_stream.next())
+        .await
+        .context("Timeout")?
+        .context("Channel closed")?
+        .context("Websocket error")?
+        .into_data();
+    serde_json::from_slice::<T>(&msg[..]).context("Failed to deserialize message")
+}
+
+/// Minimal Server-Sent Events reader.
+pub struct EventStream {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl EventStream {
+    pub fn new(response: reqwest::Response) -> Self {
+        Self {
+            response,
+            buffer: String::new(),
+        }
+    }
+
+    /// Returns the id and data of the next event, skipping comments.
+    pub async fn next_event(&mut self) -> anyhow::Result<(Option<String>, serde_json::Value)> {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let event = self.buffer[..end].to_owned();
+                self.buffer.drain(..end + 2);
+                let mut id = None;
+                let mut data = String::new();
+                for line in event.lines() {
+                    if let Some(value) = line.strip_prefix("id:") {
+                        id = Some(value.trim().to_owned());
+                    } else if let Some(value) = line.strip_prefix("data:") {
+                        data.push_str(value.trim());
+                    }
+                }
+                if data.is_empty() {
+                    continue;
+                }
+                let data = serde_json::from_str(&data).context("Failed to deserialize event")?;
+                return Ok((id, data));
+            }
+            let chunk = timeout(Duration::from_secs(1), self.response.chunk())
+                .await
+                .context("Timeout")??
+                .context("Stream closed")?;
+            self.buffer.push_str(std::str::from_utf8(&chunk)?);
+        }
+    }
+}
+
+pub async fn receive_binary(ws_stream: &mut SplitStream<WsStream>) -> anyhow::Result<Vec<u8>> {
+    let msg = timeout(Duration::from_secs(1), ws_stream.next())
+        .await
+        .context("Timeout")?
+        .context("Channel closed")?
+        .context("Websocket error")?;
+    match msg {
+        Message::Binary(data) => Ok(data),
+        other => anyhow::bail!("Expected a binary message, got {other:?}"),
+    }
+}
+
+/// Skips incoming messages until one of the given type arrives.
+pub async fn receive_message_of_type(
+    ws_stream: &mut SplitStream<WsStream>,
+    message_type: &str,
+) -> anyhow::Result<serde_json::Value> {
+    loop {
+        let message = receive_message::<serde_json::Value>(ws_stream).await?;
+        if message["type"] == message_type {
+            return Ok(message);
+        }
+    }
+}
+
+/// Skips incoming messages until the list's presence counts `users` users, and returns it.
+pub async fn receive_presence_of(
+    ws_stream: &mut SplitStream<WsStream>,
+    users: usize,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    loop {
+        let presence = receive_message_of_type(ws_stream, "presence").await?;
+        if let serde_json::Value::Array(presence) = presence["data"].clone() {
+            if presence.len() == users {
+                return Ok(presence);
+            }
+        }
+    }
+}
+
+async fn connect(
+    ws_request: hyper::http::Request<()>,
+) -> anyhow::Result<(SplitSink<WsStream, Message>, SplitStream<WsStream>)> {
+    let (ws_stream, _) = connect_async(ws_request)
+        .await
+        .context("Failed to establish ws connection")?;
+    Ok(ws_stream.split())
+}
+
 fn get_sid(cookie_jar: &Jar) -> anyhow::Result<String> {
     use reqwest::cookie::CookieStore;
 
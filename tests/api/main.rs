@@ -1,3 +1,8 @@
+mod account;
+mod chat;
 mod helpers;
+mod notification;
+mod schema;
 mod session;
+mod time;
 mod todo;
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use coodo_be::{notification::Inbox, user::User};
+use reqwest::{cookie::Jar, Client};
+
+use crate::helpers::{receive_message_of_type, receive_todo_list, TestApp};
+
+#[tokio::test]
+async fn mentions_and_assignments_notify_users() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+
+    let other_jar = Arc::new(Jar::default());
+    let mut other_client = Client::builder()
+        .cookie_provider(other_jar.clone())
+        .build()?;
+    let other_user = app.get_user(&mut other_client).await?;
+    let (_other_sink, mut other_stream) =
+        app.connect_to_todo_list(todo_list_id, &other_jar).await?;
+    receive_todo_list(&mut other_stream).await?;
+
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+    let applied = client
+        .post(&commands_url)
+        .json(&serde_json::json!({ "type": "create_task" }))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let task = applied["list"]["tasks"][0]["id"].clone();
+    let task_command = |action: &str, data: serde_json::Value| {
+        serde_json::json!({
+            "type": "task_command",
+            "data": { "task": task, "action": action, "data": data }
+        })
+    };
+    let mention = |handle: &coodo_be::user::UserHandle| format!("Ask @{}", handle.as_ref());
+
+    client
+        .post(&commands_url)
+        .json(&task_command(
+            "set_description",
+            format!(
+                "{} about it, and @{}",
+                mention(other_user.handle()),
+                user.handle().as_ref()
+            )
+            .into(),
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    let notification = receive_message_of_type(&mut other_stream, "notification").await?;
+    assert_eq!(notification["data"]["kind"], "mention");
+    assert_eq!(notification["data"]["field"], "description");
+    assert_eq!(notification["data"]["task"], task);
+    assert_eq!(notification["data"]["by"], serde_json::to_value(&user)?);
+
+    // Users already mentioned aren't notified again
+    client
+        .post(&commands_url)
+        .json(&task_command(
+            "set_description",
+            format!("{} today", mention(other_user.handle())).into(),
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    client
+        .post(&commands_url)
+        .json(&task_command(
+            "set_assignee",
+            serde_json::to_value(&other_user)?,
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    let notification = receive_message_of_type(&mut other_stream, "notification").await?;
+    assert_eq!(notification["data"]["kind"], "assignment");
+
+    let inbox_url = format!("{}/notifications", app.address);
+    let inbox = other_client
+        .get(&inbox_url)
+        .send()
+        .await?
+        .json::<Inbox>()
+        .await?;
+    assert_eq!(inbox.unread, 2);
+    let kinds = inbox
+        .notifications
+        .iter()
+        .map(|notification| serde_json::to_value(notification).map(|n| n["kind"].clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(kinds, ["assignment", "mention"]);
+    // Nobody is notified of their own mentions
+    let inbox = client.get(&inbox_url).send().await?.json::<Inbox>().await?;
+    assert!(inbox.notifications.is_empty());
+
+    let latest = other_client
+        .get(&inbox_url)
+        .send()
+        .await?
+        .json::<Inbox>()
+        .await?
+        .notifications
+        .first()
+        .context("No notification")?
+        .id();
+    let response = other_client
+        .post(format!("{inbox_url}/{latest}/read"))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 204);
+    let unread = other_client
+        .get(format!("{inbox_url}/unread"))
+        .send()
+        .await?
+        .json::<usize>()
+        .await?;
+    assert_eq!(unread, 1);
+
+    let response = other_client
+        .post(format!("{inbox_url}/read"))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 204);
+    let inbox = other_client
+        .get(&inbox_url)
+        .send()
+        .await?
+        .json::<Inbox>()
+        .await?;
+    assert_eq!(inbox.unread, 0);
+    assert!(inbox.notifications.iter().all(|n| n.is_read()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn notifications_require_a_session() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let response = Client::new()
+        .get(format!("{}/notifications", app.address))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 401);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn users_can_be_mentioned_once_they_take_part_in_a_list() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+    let user = app.get_user(&mut client).await?;
+    let handle = user.handle().as_ref();
+    assert_eq!(User::from_handle(handle, app.redis_pool()).await?, None);
+
+    app.create_todo_list(&mut client).await?;
+    assert_eq!(
+        User::from_handle(handle, app.redis_pool()).await?,
+        Some(user.clone())
+    );
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use reqwest::{Client, Method};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+
+/// Compares `generated` with the committed schema at `schema/<file>`,
+/// rewriting the latter instead when `UPDATE_SCHEMA` is set.
+fn check_committed_schema(file: &str, generated: &Value) -> anyhow::Result<()> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("schema")
+        .join(file);
+    let generated = format!("{}\n", serde_json::to_string_pretty(generated)?);
+    if std::env::var_os("UPDATE_SCHEMA").is_some() {
+        std::fs::write(&path, generated)?;
+        return Ok(());
+    }
+
+    let committed = std::fs::read_to_string(&path)?;
+    assert!(
+        committed == generated,
+        "{} is out of date, rerun the tests with UPDATE_SCHEMA=1",
+        path.display()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn committed_openapi_document_is_up_to_date() -> anyhow::Result<()> {
+    check_committed_schema("openapi.json", &coodo_be::routes::openapi())
+}
+
+/// Calls every operation of the OpenAPI document, with placeholder parameters and no body,
+/// with and without a session, and checks that the router answers with documented statuses.
+#[tokio::test]
+async fn router_answers_every_operation_as_documented() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let openapi = coodo_be::routes::openapi();
+    let paths = openapi["paths"].as_object().context("No paths")?;
+    assert!(!paths.is_empty());
+
+    for (path, operations) in paths {
+        let url = path
+            .replace("{id}", &Uuid::new_v4().to_string())
+            .replace("{name}", "missing");
+        for (method, operation) in operations.as_object().context("No operations")? {
+            let method = Method::from_bytes(method.to_uppercase().as_bytes())?;
+            for with_session in [true, false] {
+                let mut client = Client::builder().cookie_store(true).build()?;
+                if with_session {
+                    app.get_user(&mut client).await?;
+                }
+                let status = client
+                    .request(method.clone(), format!("{}{url}", app.address))
+                    .send()
+                    .await?
+                    .status();
+                assert!(
+                    operation["responses"][status.as_str()].is_object(),
+                    "{method} {path} answered {status}, which isn't documented \
+                     (with session: {with_session})"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn committed_ws_schema_matches_messages() -> anyhow::Result<()> {
+    check_committed_schema("ws.json", &coodo_be::routes::ws_schema())
+}
+
+#[tokio::test]
+async fn schemas_are_served() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let client = Client::new();
+
+    let openapi = client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Value>()
+        .await?;
+    assert_eq!(openapi, coodo_be::routes::openapi());
+
+    let ws_schema = client
+        .get(format!("{}/schema/ws.json", &app.address))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Value>()
+        .await?;
+    assert_eq!(ws_schema, coodo_be::routes::ws_schema());
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use coodo_be::todo::{
+    Command, ListTimeReport, TaskCommand, TaskCommandMeta, TimeEntry, UserTimeReport,
+};
+use futures_util::{stream::SplitSink, SinkExt};
+use reqwest::{cookie::Jar, Client};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use crate::helpers::{receive_message_of_type, receive_todo_list, send_command, TestApp, WsStream};
+
+fn task_command(task: Uuid, command: TaskCommand) -> Command {
+    Command::TaskCommand(TaskCommandMeta { task, command })
+}
+
+/// Sends a timer action on `task`, which is acknowledged without changing the list.
+async fn send_timer_action(
+    ws_sink: &mut SplitSink<WsStream, Message>,
+    task: Uuid,
+    action: &str,
+) -> anyhow::Result<()> {
+    let command = serde_json::json!({
+        "id": action,
+        "type": "task_command",
+        "data": { "task": task, "action": action },
+    });
+    ws_sink.send(Message::Text(command.to_string())).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn timers_are_tracked_per_task() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let user = app.get_user(&mut client).await?;
+    let todo_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    let task = receive_todo_list(&mut ws_stream).await?.tasks()[0].id();
+    send_command(
+        &mut ws_sink,
+        &task_command(
+            task,
+            TaskCommand::SetEstimate(Some(Duration::from_secs(3600))),
+        ),
+    )
+    .await?;
+    let revision = receive_todo_list(&mut ws_stream).await?.revision();
+    send_timer_action(&mut ws_sink, task, "start_timer").await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["revision"], revision);
+
+    let running = client
+        .get(format!("{}/time/running", app.address))
+        .send()
+        .await?
+        .json::<Option<TimeEntry>>()
+        .await?
+        .context("No running timer")?;
+    assert_eq!(running.task(), task);
+    assert_eq!(running.user(), &user);
+
+    send_timer_action(&mut ws_sink, task, "stop_timer").await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["revision"], revision);
+
+    let report = client
+        .get(format!("{}/todos/{}/time", app.address, todo_id))
+        .send()
+        .await?
+        .json::<ListTimeReport>()
+        .await?;
+    assert_eq!(report.estimate, Duration::from_secs(3600));
+    assert_eq!(report.tasks.len(), 1);
+    assert!(report.tasks[0].running.is_empty());
+
+    let report = client
+        .get(format!("{}/time/report", app.address))
+        .send()
+        .await?
+        .json::<UserTimeReport>()
+        .await?;
+    assert_eq!(report.entries.len(), 1);
+    assert!(!report.entries[0].is_running());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn starting_a_timer_stops_the_running_one_in_other_lists() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let mut tasks = vec![];
+    let mut connections = vec![];
+    for _ in 0..2 {
+        let todo_id = app.create_todo_list(&mut client).await?;
+        let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_id, &jar).await?;
+        receive_todo_list(&mut ws_stream).await?;
+        send_command(&mut ws_sink, &Command::CreateTask).await?;
+        let task = receive_todo_list(&mut ws_stream).await?.tasks()[0].id();
+        send_timer_action(&mut ws_sink, task, "start_timer").await?;
+        receive_message_of_type(&mut ws_stream, "ack").await?;
+        tasks.push((todo_id, task));
+        connections.push((ws_sink, ws_stream));
+    }
+
+    let running = client
+        .get(format!("{}/time/running", app.address))
+        .send()
+        .await?
+        .json::<Option<TimeEntry>>()
+        .await?
+        .context("No running timer")?;
+    assert_eq!((running.list(), running.task()), tasks[1]);
+
+    let first_list_report = client
+        .get(format!("{}/todos/{}/time", app.address, tasks[0].0))
+        .send()
+        .await?
+        .json::<ListTimeReport>()
+        .await?;
+    assert!(first_list_report.tasks[0].running.is_empty());
+
+    let report = client
+        .get(format!("{}/time/report", app.address))
+        .send()
+        .await?
+        .json::<UserTimeReport>()
+        .await?;
+    assert_eq!(report.entries.len(), 2);
+    assert_eq!(report.entries.iter().filter(|e| e.is_running()).count(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interleaved_timer_commands_log_each_entry_once() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let user = app.get_user(&mut client).await?;
+    let (first, second) = (
+        (Uuid::new_v4(), Uuid::new_v4()),
+        (Uuid::new_v4(), Uuid::new_v4()),
+    );
+    let rounds = 10;
+    for _ in 0..rounds {
+        TimeEntry::start_timer(user.clone(), first.0, first.1, app.redis_pool()).await?;
+        let (started, stopped) = tokio::join!(
+            TimeEntry::start_timer(user.clone(), second.0, second.1, app.redis_pool()),
+            TimeEntry::stop_timer(&user, first.0, first.1, app.redis_pool()),
+        );
+        stopped?;
+        started?;
+    }
+
+    let running = TimeEntry::running_timer(*user.id(), app.redis_pool())
+        .await?
+        .context("No running timer")?;
+    assert_eq!((running.list(), running.task()), second);
+    let first_entries = TimeEntry::list_entries(first.0, app.redis_pool()).await?;
+    assert_eq!(first_entries.len(), rounds);
+    assert!(first_entries.iter().all(|entry| !entry.is_running()));
+    let second_entries = TimeEntry::list_entries(second.0, app.redis_pool()).await?;
+    assert_eq!(second_entries.len(), rounds);
+    assert_eq!(second_entries.iter().filter(|e| e.is_running()).count(), 1);
+
+    Ok(())
+}
@@ -1,13 +1,21 @@
 use std::{sync::Arc, time::Duration};
 
 use anyhow::Context;
-use coodo_be::todo::{Command, TaskCommandMeta, TodoList};
-use futures_util::{SinkExt, StreamExt};
+use coodo_be::{
+    message::ServerMessage,
+    state::AppState,
+    todo::{Command, TaskCommand, TaskCommandMeta, TodoList, TodoListStats},
+};
+use futures_util::SinkExt;
 use reqwest::{cookie::Jar, Client};
 use tokio::time::timeout;
 use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
 
-use crate::helpers::TestApp;
+use crate::helpers::{
+    receive_binary, receive_message, receive_message_of_type, receive_presence_of,
+    receive_todo_list, send_command, EventStream, TestApp,
+};
 
 #[tokio::test]
 async fn create_todo_without_session_returns_401() -> anyhow::Result<()> {
@@ -43,39 +51,19 @@ async fn todo_list_workflow_works() -> anyhow::Result<()> {
     let jar = Arc::new(Jar::default());
     let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
 
-    let user = app.get_user(&mut client).await?;
+    let _user = app.get_user(&mut client).await?;
     let todo_list_id = app.create_todo_list(&mut client).await?;
     let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
 
-    let todo_list = {
-        let msg = timeout(Duration::from_secs(1), ws_stream.next())
-            .await
-            .context("Timeout")?
-            .context("No message")?
-            .context("Websocket error")?
-            .into_data();
-        serde_json::from_slice::<TodoList>(&msg[..]).context("Failed to deserialize todo list")?
-    };
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
 
     assert_eq!(todo_list.id(), todo_list_id);
-    assert!(todo_list
-        .connected_users()
-        .get(0)
-        .is_some_and(|u| u.id() == user.id()));
 
     ws_sink
         .send(Message::Binary(serde_json::to_vec(&Command::CreateTask)?))
         .await?;
 
-    let todo_list_updated = {
-        let msg = timeout(Duration::from_secs(1), ws_stream.next())
-            .await
-            .context("Timeout")?
-            .context("Channel closed")?
-            .context("Websocket error")?
-            .into_data();
-        serde_json::from_slice::<TodoList>(&msg[..]).context("Failed to deserialize todo list")?
-    };
+    let todo_list_updated = receive_todo_list(&mut ws_stream).await?;
     assert_eq!(todo_list.id(), todo_list_updated.id());
     assert_eq!(todo_list_updated.tasks().len(), 1);
 
@@ -88,15 +76,7 @@ async fn todo_list_workflow_works() -> anyhow::Result<()> {
         .send(Message::Binary(serde_json::to_vec(&rename_task)?))
         .await?;
 
-    let todo_list_updated = {
-        let msg = timeout(Duration::from_secs(1), ws_stream.next())
-            .await
-            .context("Timeout")?
-            .context("Channel closed")?
-            .context("Websocket error")?
-            .into_data();
-        serde_json::from_slice::<TodoList>(&msg[..]).context("Failed to deserialize todo list")?
-    };
+    let todo_list_updated = receive_todo_list(&mut ws_stream).await?;
     assert_eq!(todo_list_updated.tasks()[0].name(), "my task");
     Ok(())
 }
@@ -138,3 +118,1414 @@ async fn user_delete_todos_works() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn clone_todo_list_copies_tasks() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    ws_sink
+        .send(Message::Binary(serde_json::to_vec(&Command::CreateTask)?))
+        .await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    let set_done = Command::TaskCommand(TaskCommandMeta {
+        task: todo_list.tasks()[0].id(),
+        command: coodo_be::todo::TaskCommand::SetDone(true),
+    });
+    ws_sink
+        .send(Message::Binary(serde_json::to_vec(&set_done)?))
+        .await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    let clone_id = client
+        .post(format!("{}/todos/{}/clone", app.address, todo_list_id))
+        .json(&serde_json::json!({ "reset_done": true }))
+        .send()
+        .await
+        .context("Failed to send POST /todos/:id/clone")?
+        .json::<Uuid>()
+        .await?;
+    assert_ne!(clone_id, todo_list_id);
+
+    let clone = TodoList::from_redis(clone_id, app.redis_pool()).await?;
+    assert_eq!(clone.tasks().len(), 1);
+    assert_ne!(clone.tasks()[0].id(), todo_list.tasks()[0].id());
+    assert!(!clone.tasks()[0].is_done());
+
+    let joined_todos = app.get_joined_todo_lists(&client).await?;
+    assert!(joined_todos.iter().any(|list| list.info().id() == clone_id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn create_todo_from_template_works() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    ws_sink
+        .send(Message::Binary(serde_json::to_vec(&Command::CreateTask)?))
+        .await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    let response = client
+        .post(format!("{}/templates", app.address))
+        .json(&serde_json::json!({ "list": todo_list_id, "name": "packing" }))
+        .send()
+        .await
+        .context("Failed to send POST /templates")?;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let templates = client
+        .get(format!("{}/templates", app.address))
+        .send()
+        .await?
+        .json::<Vec<String>>()
+        .await?;
+    assert_eq!(templates, vec!["packing".to_owned()]);
+
+    let new_list_id = client
+        .post(format!("{}/todos?template=packing", app.address))
+        .send()
+        .await
+        .context("Failed to send POST /todos?template=")?
+        .json::<Uuid>()
+        .await?;
+    let new_list = TodoList::from_redis(new_list_id, app.redis_pool()).await?;
+    assert_eq!(new_list.tasks().len(), 1);
+
+    let joined_todos = app.get_joined_todo_lists(&client).await?;
+    assert_eq!(joined_todos.len(), 2);
+
+    let response = client
+        .post(format!("{}/todos?template=missing", app.address))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn task_dependencies_are_enforced() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    let (_other_sink, mut other_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    let (blocker, blocked) = (todo_list.tasks()[0].id(), todo_list.tasks()[1].id());
+
+    let task_command = |task, command| Command::TaskCommand(TaskCommandMeta { task, command });
+    send_command(
+        &mut ws_sink,
+        &task_command(blocked, TaskCommand::AddBlocker(blocker)),
+    )
+    .await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    assert_eq!(todo_list.task(blocked).unwrap().blocked_by(), &[blocker]);
+
+    send_command(
+        &mut ws_sink,
+        &task_command(blocker, TaskCommand::AddBlocker(blocked)),
+    )
+    .await?;
+    let error = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["data"]["reason"], "dependency_cycle");
+
+    send_command(
+        &mut ws_sink,
+        &task_command(blocked, TaskCommand::SetDone(true)),
+    )
+    .await?;
+    let error = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(error["data"]["reason"], "open_blockers");
+
+    let completion = serde_json::json!({
+        "id": "done",
+        "type": "task_command",
+        "data": { "task": blocker, "action": "set_done", "data": true },
+    });
+    ws_sink.send(Message::Text(completion.to_string())).await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["unblocked"], serde_json::json!([blocked]));
+
+    // Other connections are told too
+    let unblocked = receive_message_of_type(&mut other_stream, "unblocked").await?;
+    assert_eq!(unblocked["data"]["tasks"], serde_json::json!([blocked]));
+    assert_eq!(unblocked["data"]["revision"], ack["data"]["revision"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_get_todos_returns_list_metadata() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    let mut todo_list = receive_todo_list(&mut ws_stream).await?;
+
+    for command in [
+        Command::SetListDescription("Things to pack".to_owned()),
+        Command::SetListIcon(Some("🧳".to_owned())),
+        Command::SetListColor(Some("#ff8800".to_owned())),
+        Command::CreateTask,
+        Command::CreateTask,
+    ] {
+        send_command(&mut ws_sink, &command).await?;
+        todo_list = receive_todo_list(&mut ws_stream).await?;
+    }
+    send_command(
+        &mut ws_sink,
+        &Command::SetListColor(Some("orange".to_owned())),
+    )
+    .await?;
+    let error = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(error["data"]["reason"], "invalid_color");
+
+    send_command(
+        &mut ws_sink,
+        &Command::TaskCommand(TaskCommandMeta {
+            task: todo_list.tasks()[0].id(),
+            command: TaskCommand::SetDone(true),
+        }),
+    )
+    .await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    let check_info = |info: &coodo_be::todo::TodoListInfo| {
+        assert_eq!(info.description(), "Things to pack");
+        assert_eq!(info.icon(), Some("🧳"));
+        assert_eq!(info.color(), Some("#ff8800"));
+        assert_eq!(info.owner(), Some(&user));
+        assert_eq!(info.task_count(), 2);
+        assert_eq!(info.done_count(), 1);
+        assert!(info.last_updated_at().is_some());
+    };
+    let joined_todos = app.get_joined_todo_lists(&client).await?;
+    check_info(joined_todos[0].info());
+
+    // Once everyone left, the info is read from the stored list
+    ws_sink.close().await?;
+    timeout(Duration::from_secs(1), async {
+        while TodoList::from_redis(todo_list_id, app.redis_pool())
+            .await
+            .map_or(true, |list| list.tasks().is_empty())
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .context("List never stored")?;
+    let joined_todos = app.get_joined_todo_lists(&client).await?;
+    check_info(joined_todos[0].info());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_can_organize_joined_todos() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let first = app.create_todo_list(&mut client).await?;
+    let second = app.create_todo_list(&mut client).await?;
+    let third = app.create_todo_list(&mut client).await?;
+
+    let response = client
+        .put(format!("{}/todos/order", app.address))
+        .json(&[third, first])
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let response = client
+        .put(format!("{}/todos/{}/pinned", app.address, second))
+        .json(&true)
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let response = client
+        .put(format!("{}/todos/{}/folder", app.address, first))
+        .json(&"Work")
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let response = client
+        .put(format!("{}/todos/{}/pinned", app.address, Uuid::new_v4()))
+        .json(&true)
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let joined_todos = app.get_joined_todo_lists(&client).await?;
+    let order = joined_todos
+        .iter()
+        .map(|list| list.info().id())
+        .collect::<Vec<_>>();
+    assert_eq!(order, vec![second, third, first]);
+    assert!(joined_todos[0].is_pinned());
+    assert_eq!(joined_todos[2].folder(), Some("Work"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn todo_list_stats_work() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "stats=true", &jar)
+        .await?;
+    receive_todo_list(&mut ws_stream).await?;
+    receive_message::<serde_json::Value>(&mut ws_stream).await?;
+
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    receive_message::<serde_json::Value>(&mut ws_stream).await?;
+
+    send_command(
+        &mut ws_sink,
+        &Command::TaskCommand(TaskCommandMeta {
+            task: todo_list.tasks()[0].id(),
+            command: TaskCommand::SetDone(true),
+        }),
+    )
+    .await?;
+    receive_todo_list(&mut ws_stream).await?;
+    let message = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(message["type"], "stats");
+    let pushed_stats = serde_json::from_value::<TodoListStats>(message["data"].clone())?;
+
+    let stats = client
+        .get(format!("{}/todos/{}/stats", app.address, todo_list_id))
+        .send()
+        .await?
+        .json::<TodoListStats>()
+        .await?;
+    assert_eq!(stats.total, 2);
+    assert_eq!(stats.done, 1);
+    assert_eq!(stats.open, 1);
+    assert_eq!(stats.completion_rate, 0.5);
+    assert_eq!(stats.assignees.len(), 1);
+    assert_eq!(stats.assignees[0].user, user);
+    assert!(stats.oldest_open_task_age.is_some());
+    assert_eq!(pushed_stats.total, stats.total);
+    assert_eq!(pushed_stats.done, stats.done);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn commands_are_acknowledged() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+
+    ws_sink
+        .send(Message::Text(
+            r#"{ "id": "req-1", "type": "create_task" }"#.to_owned(),
+        ))
+        .await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["id"], "req-1");
+    assert_eq!(ack["data"]["revision"], todo_list.revision() + 1);
+
+    ws_sink
+        .send(Message::Text(
+            r#"{ "id": "req-2", "type": "not_a_command" }"#.to_owned(),
+        ))
+        .await?;
+    let error = receive_message_of_type(&mut ws_stream, "error").await?;
+    assert_eq!(error["data"]["id"], "req-2");
+    assert_eq!(error["data"]["reason"], "malformed");
+
+    let unknown_task = Uuid::new_v4();
+    ws_sink
+        .send(Message::Text(
+            serde_json::json!({
+                "id": "req-3",
+                "type": "task_command",
+                "data": { "task": unknown_task, "action": "set_done", "data": true }
+            })
+            .to_string(),
+        ))
+        .await?;
+    let error = receive_message_of_type(&mut ws_stream, "error").await?;
+    assert_eq!(error["data"]["id"], "req-3");
+    assert_eq!(error["data"]["reason"], "unknown_task");
+    assert_eq!(error["data"]["task"], unknown_task.to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn command_floods_are_rate_limited() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    for _ in 0..100 {
+        send_command(&mut ws_sink, &Command::SetListName("flood".to_owned())).await?;
+    }
+    let error = receive_message_of_type(&mut ws_stream, "error").await?;
+    assert_eq!(error["data"]["reason"], "rate_limited");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn todo_list_patches_work() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "patches=true", &jar)
+        .await?;
+
+    let snapshot = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(snapshot["type"], "snapshot");
+    let mut todo_list = snapshot["data"].clone();
+
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    let patch = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(patch["type"], "patch");
+    let ops = serde_json::from_value::<json_patch::Patch>(patch["data"]["ops"].clone())?;
+    assert!(ops.0.len() < 5, "Patch should only contain changes");
+    json_patch::patch(&mut todo_list, &ops)?;
+
+    let todo_list = serde_json::from_value::<TodoList>(todo_list)?;
+    assert_eq!(todo_list.revision(), patch["data"]["revision"]);
+    assert_eq!(todo_list.tasks().len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_commands_are_rejected() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    send_command(&mut ws_sink, &Command::CreateTask).await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    let base = todo_list.revision();
+    let (first, second) = (todo_list.tasks()[0].id(), todo_list.tasks()[1].id());
+
+    let rename = |id: &str, task: Uuid, name: &str| {
+        Message::Text(
+            serde_json::json!({
+                "id": id,
+                "expected_revision": base,
+                "type": "task_command",
+                "data": { "task": task, "action": "rename", "data": name }
+            })
+            .to_string(),
+        )
+    };
+
+    ws_sink.send(rename("req-1", first, "mine")).await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["revision"], base + 1);
+
+    // Someone else edited the first task in the meantime
+    ws_sink.send(rename("req-2", first, "theirs")).await?;
+    let error = receive_message_of_type(&mut ws_stream, "error").await?;
+    assert_eq!(error["data"]["id"], "req-2");
+    assert_eq!(error["data"]["reason"], "conflict");
+    assert_eq!(error["data"]["expected_revision"], base);
+    assert_eq!(error["data"]["revision"], base + 1);
+
+    // Edits to other tasks are merged
+    ws_sink.send(rename("req-3", second, "theirs")).await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["id"], "req-3");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reconnecting_clients_resume_from_their_revision() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "patches=true", &jar)
+        .await?;
+    let snapshot = receive_message_of_type(&mut ws_stream, "snapshot").await?;
+    let mut todo_list = snapshot["data"].clone();
+    let last_seen = todo_list["revision"].as_u64().context("No revision")?;
+
+    // A change the client misses while disconnected
+    ws_sink.close().await?;
+    let (mut other_sink, mut other_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut other_stream).await?;
+    send_command(&mut other_sink, &Command::CreateTask).await?;
+    receive_todo_list(&mut other_stream).await?;
+    other_sink.close().await?;
+
+    let (_ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_with(todo_list_id, &format!("since={last_seen}"), &jar)
+        .await?;
+    let patch = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(patch["type"], "patch");
+    assert_eq!(patch["data"]["revision"], last_seen + 1);
+    let ops = serde_json::from_value::<json_patch::Patch>(patch["data"]["ops"].clone())?;
+    json_patch::patch(&mut todo_list, &ops)?;
+
+    assert!(receive_message::<serde_json::Value>(&mut ws_stream)
+        .await
+        .is_err());
+    let todo_list = serde_json::from_value::<TodoList>(todo_list)?;
+    assert_eq!(todo_list.tasks().len(), 1);
+
+    // Clients too far behind get a snapshot
+    let (_ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "since=1000", &jar)
+        .await?;
+    receive_message_of_type(&mut ws_stream, "snapshot").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn binary_formats_are_negotiated() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+
+    let (mut ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_using(todo_list_id, "coodo.v1.msgpack", &jar)
+        .await?;
+    let decode = |data: Vec<u8>| -> anyhow::Result<ServerMessage<'static>> {
+        let mut deserializer = rmp_serde::Deserializer::new(&data[..]).with_human_readable();
+        Ok(serde::Deserialize::deserialize(&mut deserializer)?)
+    };
+    let hello = decode(receive_binary(&mut ws_stream).await?)?;
+    assert!(matches!(hello, ServerMessage::Hello { .. }));
+    let ServerMessage::Snapshot(todo_list) = decode(receive_binary(&mut ws_stream).await?)? else {
+        anyhow::bail!("Expected a snapshot");
+    };
+    assert_eq!(todo_list.id(), todo_list_id);
+
+    let mut command = vec![];
+    let mut serializer = rmp_serde::Serializer::new(&mut command)
+        .with_struct_map()
+        .with_human_readable();
+    serde::Serialize::serialize(&Command::CreateTask, &mut serializer)?;
+    ws_sink.send(Message::Binary(command)).await?;
+    let ServerMessage::Snapshot(todo_list) = decode(receive_binary(&mut ws_stream).await?)? else {
+        anyhow::bail!("Expected a snapshot");
+    };
+    assert_eq!(todo_list.tasks().len(), 1);
+    ws_sink.close().await?;
+
+    let (mut ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_using(todo_list_id, "coodo.v1.cbor", &jar)
+        .await?;
+    let hello =
+        ciborium::from_reader::<ServerMessage, _>(&receive_binary(&mut ws_stream).await?[..])?;
+    assert!(matches!(hello, ServerMessage::Hello { .. }));
+    let data = receive_binary(&mut ws_stream).await?;
+    let ServerMessage::Snapshot(todo_list) = ciborium::from_reader(&data[..])? else {
+        anyhow::bail!("Expected a snapshot");
+    };
+    assert_eq!(todo_list.tasks().len(), 1);
+
+    let mut command = vec![];
+    ciborium::into_writer(&Command::CreateTask, &mut command)?;
+    ws_sink.send(Message::Binary(command)).await?;
+    let data = receive_binary(&mut ws_stream).await?;
+    let ServerMessage::Snapshot(todo_list) = ciborium::from_reader(&data[..])? else {
+        anyhow::bail!("Expected a snapshot");
+    };
+    assert_eq!(todo_list.tasks().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() -> anyhow::Result<()> {
+    let app = TestApp::spawn_with(|settings| {
+        settings.todo_handler.heartbeat_interval = Duration::from_millis(100);
+        settings.todo_handler.idle_timeout = Duration::from_millis(300);
+    })
+    .await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+
+    let other_jar = Arc::new(Jar::default());
+    let mut other_client = Client::builder()
+        .cookie_provider(other_jar.clone())
+        .build()?;
+    let _other_user = app.get_user(&mut other_client).await?;
+
+    // Never reading from the stream means never answering pings
+    let (_ws_sink, _ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    let (_other_sink, mut other_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "presence=true", &other_jar)
+        .await?;
+
+    receive_presence_of(&mut other_stream, 2).await?;
+
+    timeout(Duration::from_secs(2), async {
+        while receive_presence_of(&mut other_stream, 1).await.is_err() {}
+    })
+    .await
+    .context("Idle user never left the list")?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn application_pings_are_answered() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    ws_sink
+        .send(Message::Text(r#"{ "type": "ping" }"#.to_owned()))
+        .await?;
+    let pong = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(pong, serde_json::json!({ "type": "pong" }));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn users_can_connect_several_times() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (mut first_sink, mut first_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "presence=true", &jar)
+        .await?;
+    receive_todo_list(&mut first_stream).await?;
+    receive_presence_of(&mut first_stream, 1).await?;
+
+    let (mut second_sink, mut second_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "presence=true", &jar)
+        .await?;
+    receive_todo_list(&mut second_stream).await?;
+    let presence = receive_presence_of(&mut second_stream, 1).await?;
+    assert_eq!(presence[0]["user"], serde_json::to_value(&user)?);
+
+    // Both connections stay alive and get every change
+    send_command(&mut second_sink, &Command::CreateTask).await?;
+    assert_eq!(receive_todo_list(&mut first_stream).await?.tasks().len(), 1);
+    assert_eq!(
+        receive_todo_list(&mut second_stream).await?.tasks().len(),
+        1
+    );
+
+    // Closing one of them doesn't make the user leave
+    second_sink.close().await?;
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    send_command(&mut first_sink, &Command::CreateTask).await?;
+    assert_eq!(receive_todo_list(&mut first_stream).await?.tasks().len(), 2);
+    // No presence change either
+    assert!(receive_message::<serde_json::Value>(&mut first_stream)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn commands_can_be_sent_over_http() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let (_ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+
+    let response = client
+        .post(format!("{}/todos/{}/commands", app.address, todo_list_id))
+        .json(&Command::CreateTask)
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 200);
+    let applied = response.json::<serde_json::Value>().await?;
+    let todo_list = serde_json::from_value::<TodoList>(applied["list"].clone())?;
+    assert_eq!(todo_list.tasks().len(), 1);
+    assert_eq!(applied["revision"], todo_list.revision());
+
+    // Connected users see the change
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    assert_eq!(todo_list.tasks().len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn command_batches_are_applied_in_order() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+
+    let response = client
+        .post(&commands_url)
+        .json(&serde_json::json!([
+            { "type": "create_task" },
+            { "type": "set_list_name", "data": "Chores" },
+        ]))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 200);
+    let revision = response.json::<serde_json::Value>().await?["revision"]
+        .as_u64()
+        .context("No revision")?;
+
+    // Nobody is connected, so the list has been stored right away
+    let todo_list = TodoList::from_redis(todo_list_id, app.redis_pool()).await?;
+    assert_eq!(todo_list.name(), "Chores");
+    assert_eq!(todo_list.tasks().len(), 1);
+    assert_eq!(todo_list.revision(), revision);
+
+    let response = client
+        .post(&commands_url)
+        .json(&serde_json::json!([
+            { "type": "set_list_name", "data": "Errands", "expected_revision": revision },
+            { "id": "stale", "type": "set_list_name", "data": "Groceries", "expected_revision": revision },
+        ]))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 409);
+    let rejected = response.json::<serde_json::Value>().await?;
+    assert_eq!(rejected["id"], "stale");
+    assert_eq!(rejected["applied"], 1);
+    assert_eq!(rejected["reason"], "conflict");
+
+    let todo_list = TodoList::from_redis(todo_list_id, app.redis_pool()).await?;
+    assert_eq!(todo_list.name(), "Errands");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_command_batches_are_all_stored() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+
+    let batches = (0..8).map(|_| {
+        client
+            .post(&commands_url)
+            .json(&serde_json::json!([{ "type": "create_task" }, { "type": "create_task" }]))
+            .send()
+    });
+    for response in futures_util::future::join_all(batches).await {
+        let applied = response?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        let todo_list = serde_json::from_value::<TodoList>(applied["list"].clone())?;
+        // Each batch sees at least its own tasks
+        assert!(todo_list.tasks().len() >= 2);
+    }
+
+    let todo_list = TodoList::from_redis(todo_list_id, app.redis_pool()).await?;
+    assert_eq!(todo_list.tasks().len(), 16);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn todo_list_events_are_streamed() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let events_url = format!("{}/todos/{}/events", app.address, todo_list_id);
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+
+    let response = client.get(&events_url).send().await?;
+    assert_eq!(response.status().as_u16(), 200);
+    let mut events = EventStream::new(response);
+    let (_, hello) = events.next_event().await?;
+    assert_eq!(hello["type"], "hello");
+    let (last_event_id, snapshot) = events.next_event().await?;
+    assert_eq!(snapshot["type"], "snapshot");
+    let last_event_id = last_event_id.context("Snapshots must have an id")?;
+
+    client
+        .post(&commands_url)
+        .json(&Command::CreateTask)
+        .send()
+        .await?
+        .error_for_status()?;
+    let (_, created) = events.next_event().await?;
+    assert_eq!(created["type"], "patch");
+    drop(events);
+
+    // Reconnecting within the grace period resumes without any presence churn
+    let response = client
+        .get(format!("{events_url}?presence=true"))
+        .header("Last-Event-ID", &last_event_id)
+        .send()
+        .await?;
+    let mut events = EventStream::new(response);
+    let (_, hello) = events.next_event().await?;
+    assert_eq!(hello["type"], "hello");
+    let (_, missed) = events.next_event().await?;
+    assert_eq!(missed, created);
+    let (_, presence) = events.next_event().await?;
+    assert_eq!(presence["type"], "presence");
+    assert_eq!(presence["data"][0]["user"], serde_json::to_value(&user)?);
+    assert!(events.next_event().await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn presence_is_broadcast_without_touching_the_list() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+
+    let other_jar = Arc::new(Jar::default());
+    let mut other_client = Client::builder()
+        .cookie_provider(other_jar.clone())
+        .build()?;
+    let other_user = app.get_user(&mut other_client).await?;
+
+    let (_ws_sink, mut ws_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "presence=true", &jar)
+        .await?;
+    let todo_list = receive_todo_list(&mut ws_stream).await?;
+    receive_presence_of(&mut ws_stream, 1).await?;
+
+    let (mut other_sink, mut other_stream) =
+        app.connect_to_todo_list(todo_list_id, &other_jar).await?;
+    receive_todo_list(&mut other_stream).await?;
+    receive_presence_of(&mut ws_stream, 2).await?;
+
+    let task = Uuid::new_v4();
+    other_sink
+        .send(Message::Text(
+            serde_json::json!({
+                "type": "presence",
+                "data": { "status": "active", "editing": task, "typing": true }
+            })
+            .to_string(),
+        ))
+        .await?;
+    let presence = receive_message::<serde_json::Value>(&mut ws_stream).await?;
+    assert_eq!(presence["type"], "presence");
+    let others = presence["data"]
+        .as_array()
+        .context("Presence is a list")?
+        .iter()
+        .find(|presence| presence["user"] == serde_json::to_value(&other_user).unwrap())
+        .context("Other user is present")?;
+    assert_eq!(others["editing"], task.to_string());
+    assert_eq!(others["typing"], true);
+    assert_eq!(others["viewing"], serde_json::Value::Null);
+
+    // Presence never changes the list itself
+    assert!(receive_message::<serde_json::Value>(&mut other_stream)
+        .await
+        .is_err());
+    other_sink.close().await?;
+    let presence = receive_presence_of(&mut ws_stream, 1).await?;
+    assert_eq!(presence[0]["user"], serde_json::to_value(&user)?);
+
+    let stored = TodoList::from_redis(todo_list_id, app.redis_pool()).await?;
+    assert_eq!(stored.revision(), todo_list.revision());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_text_edits_are_merged() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+
+    let applied = client
+        .post(&commands_url)
+        .json(&Command::CreateTask)
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let task = applied["list"]["tasks"][0]["id"].clone();
+    let task_command = |action: &str, data: serde_json::Value| {
+        serde_json::json!({
+            "type": "task_command",
+            "data": { "task": task, "action": action, "data": data }
+        })
+    };
+    let applied = client
+        .post(&commands_url)
+        .json(&serde_json::json!([
+            task_command("rename", "buy milk".into()),
+            task_command("set_description", "at the store".into()),
+        ]))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let base = applied["revision"].clone();
+
+    // Two users typing in the same task, neither having seen the other's edits
+    let edit = |field: &str, ops: serde_json::Value| {
+        task_command(
+            "edit_text",
+            serde_json::json!({ "field": field, "base_revision": base, "ops": ops }),
+        )
+    };
+    let response = client
+        .post(&commands_url)
+        .json(&serde_json::json!([
+            edit(
+                "name",
+                serde_json::json!([{ "retain": 4 }, { "insert": "oat " }])
+            ),
+            edit(
+                "name",
+                serde_json::json!([{ "insert": "Go " }, { "delete": 4 }])
+            ),
+            edit(
+                "description",
+                serde_json::json!([{ "retain": 7 }, { "delete": 5 }, { "insert": "corner" }])
+            ),
+            edit(
+                "description",
+                serde_json::json!([{ "retain": 12 }, { "insert": " shop" }])
+            ),
+        ]))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Nobody is connected, so the merged texts have been stored right away
+    let todo_list = TodoList::from_redis(todo_list_id, app.redis_pool()).await?;
+    assert_eq!(todo_list.tasks()[0].name(), "Go oat milk");
+    assert_eq!(todo_list.tasks()[0].description(), "at the corner shop");
+
+    // Edits can't be rebased on changes made before the list was loaded again
+    let response = client
+        .post(&commands_url)
+        .json(&edit("name", serde_json::json!([{ "insert": "!" }])))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 409);
+
+    // Edits past the end of the text are rejected
+    let response = client
+        .post(&commands_url)
+        .json(&task_command(
+            "edit_text",
+            serde_json::json!({
+                "field": "name",
+                "base_revision": todo_list.revision(),
+                "ops": [{ "retain": 100 }]
+            }),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 422);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn offline_commands_are_merged() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    // Someone stays online, keeping the list loaded
+    let (_ws_sink, _ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+    let applied = client
+        .post(&commands_url)
+        .json(&serde_json::json!([{ "type": "create_task" }, { "type": "create_task" }]))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let (edited, untouched) = (
+        applied["list"]["tasks"][0]["id"].clone(),
+        applied["list"]["tasks"][1]["id"].clone(),
+    );
+    let applied = client
+        .post(&commands_url)
+        .json(&serde_json::json!({
+            "type": "task_command",
+            "data": { "task": edited, "action": "rename", "data": "milk" }
+        }))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let base = applied["revision"].clone();
+    let went_offline = chrono::Utc::now();
+
+    // Edited online while the other client is offline
+    client
+        .post(&commands_url)
+        .json(&serde_json::json!({
+            "type": "task_command",
+            "data": { "task": edited, "action": "rename", "data": "oat milk" }
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let offline =
+        |id: &str, issued_at: chrono::DateTime<chrono::Utc>, command: serde_json::Value| {
+            let mut command = command;
+            command["id"] = id.into();
+            command["issued_at"] = serde_json::to_value(issued_at).unwrap();
+            command
+        };
+    let task_command = |task: &serde_json::Value, action: &str, data: serde_json::Value| {
+        serde_json::json!({
+            "type": "task_command",
+            "data": { "task": task, "action": action, "data": data }
+        })
+    };
+    let minutes = chrono::Duration::minutes;
+    // Merged in the order they are sent, whatever the client's clock says
+    let response = client
+        .post(format!("{}/todos/{}/merge", app.address, todo_list_id))
+        .json(&serde_json::json!({
+            "base_revision": base,
+            "commands": [
+                offline("list", went_offline + minutes(10), serde_json::json!({ "type": "set_list_name", "data": "Offline" })),
+                offline("rename", went_offline, task_command(&edited, "rename", "offline".into())),
+                offline("edit", went_offline + minutes(1), task_command(&edited, "edit_text", serde_json::json!({
+                    "field": "name",
+                    "base_revision": base,
+                    "ops": [{ "retain": 4 }, { "insert": "!" }]
+                }))),
+                offline("done", went_offline + minutes(2), task_command(&untouched, "set_done", true.into())),
+                offline("unknown", went_offline + minutes(3), task_command(&serde_json::json!(Uuid::new_v4()), "set_done", true.into())),
+            ]
+        }))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 200);
+    let report = response.json::<serde_json::Value>().await?;
+
+    let outcomes = report["results"]
+        .as_array()
+        .context("No results")?
+        .iter()
+        .map(|result| {
+            (
+                result["id"].as_str().unwrap(),
+                result["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        [
+            ("list", "applied"),
+            ("rename", "rejected"),
+            ("edit", "transformed"),
+            ("done", "applied"),
+            ("unknown", "rejected"),
+        ]
+    );
+    assert_eq!(report["results"][1]["reason"], "superseded");
+    assert_eq!(report["results"][4]["reason"], "unknown_task");
+
+    let todo_list = serde_json::from_value::<TodoList>(report["list"].clone())?;
+    assert_eq!(todo_list.name(), "Offline");
+    assert_eq!(todo_list.tasks()[0].name(), "oat milk!");
+    assert!(todo_list.tasks()[1].is_done());
+    assert_eq!(report["revision"], todo_list.revision());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn offline_commands_can_refer_to_tasks_created_offline() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let issued_at = chrono::Utc::now();
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    let task_command = |task: Uuid, action: &str, data: serde_json::Value| {
+        serde_json::json!({
+            "issued_at": issued_at,
+            "type": "task_command",
+            "data": { "task": task, "action": action, "data": data }
+        })
+    };
+    let report = client
+        .post(format!("{}/todos/{}/merge", app.address, todo_list_id))
+        .json(&serde_json::json!({
+            "base_revision": 0,
+            "commands": [
+                { "issued_at": issued_at, "type": "create_task", "local_task": first },
+                { "issued_at": issued_at, "type": "create_task", "local_task": second },
+                task_command(first, "rename", "milk".into()),
+                task_command(second, "add_blocker", serde_json::json!(first)),
+            ]
+        }))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+
+    let results = report["results"].as_array().context("No results")?;
+    assert!(results.iter().all(|result| result["outcome"] == "applied"));
+    let todo_list = serde_json::from_value::<TodoList>(report["list"].clone())?;
+    let tasks = todo_list.tasks();
+    assert_eq!(results[0]["created_task"], serde_json::json!(tasks[0].id()));
+    assert_eq!(results[1]["created_task"], serde_json::json!(tasks[1].id()));
+    assert_eq!(tasks[0].name(), "milk");
+    assert_eq!(tasks[1].blocked_by(), [tasks[0].id()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn offline_text_edits_to_changed_tasks_conflict_once_the_list_is_unloaded(
+) -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let commands_url = format!("{}/todos/{}/commands", app.address, todo_list_id);
+    let applied = client
+        .post(&commands_url)
+        .json(&Command::CreateTask)
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let task = applied["list"]["tasks"][0]["id"].clone();
+    let base = applied["revision"].clone();
+
+    // Nobody is connected, so the list is unloaded after the rename along with its edits
+    client
+        .post(&commands_url)
+        .json(&serde_json::json!({
+            "type": "task_command",
+            "data": { "task": task, "action": "rename", "data": "milk" }
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let report = client
+        .post(format!("{}/todos/{}/merge", app.address, todo_list_id))
+        .json(&serde_json::json!({
+            "base_revision": base,
+            "commands": [{
+                "issued_at": chrono::Utc::now(),
+                "type": "task_command",
+                "data": {
+                    "task": task,
+                    "action": "edit_text",
+                    "data": { "field": "name", "base_revision": base, "ops": [{ "insert": "oat" }] }
+                }
+            }]
+        }))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    assert_eq!(report["results"][0]["outcome"], "rejected");
+    assert_eq!(report["results"][0]["reason"], "conflict");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn claimed_tasks_can_only_be_edited_by_their_holder() -> anyhow::Result<()> {
+    let app = TestApp::spawn_with(|settings| {
+        settings.todo_handler.lease_duration = Duration::from_millis(500);
+    })
+    .await;
+    let jar = Arc::new(Jar::default());
+    let mut client = Client::builder().cookie_provider(jar.clone()).build()?;
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+
+    let other_jar = Arc::new(Jar::default());
+    let mut other_client = Client::builder()
+        .cookie_provider(other_jar.clone())
+        .build()?;
+    let other_user = app.get_user(&mut other_client).await?;
+
+    let (mut ws_sink, mut ws_stream) = app.connect_to_todo_list(todo_list_id, &jar).await?;
+    receive_todo_list(&mut ws_stream).await?;
+    let (mut other_sink, mut other_stream) = app
+        .connect_to_todo_list_with(todo_list_id, "presence=true", &other_jar)
+        .await?;
+    receive_todo_list(&mut other_stream).await?;
+    receive_presence_of(&mut other_stream, 2).await?;
+
+    let applied = client
+        .post(format!("{}/todos/{}/commands", app.address, todo_list_id))
+        .json(&Command::CreateTask)
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let task = applied["list"]["tasks"][0]["id"].clone();
+    let request = |id: &str, command: serde_json::Value| {
+        let mut command = command;
+        command["id"] = id.into();
+        Message::Text(command.to_string())
+    };
+    let rename = |name: &str| {
+        serde_json::json!({
+            "type": "task_command",
+            "data": { "task": task, "action": "rename", "data": name }
+        })
+    };
+
+    ws_sink
+        .send(request(
+            "claim",
+            serde_json::json!({ "type": "claim_task", "data": task }),
+        ))
+        .await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["id"], "claim");
+    // Claims don't change the list
+    assert_eq!(ack["data"]["revision"], applied["revision"]);
+    let presence = receive_message_of_type(&mut other_stream, "presence").await?;
+    let holder = presence["data"]
+        .as_array()
+        .context("Presence is a list")?
+        .iter()
+        .find(|presence| presence["user"] == serde_json::to_value(&user).unwrap())
+        .context("User is present")?;
+    assert_eq!(holder["lease"]["task"], task);
+
+    other_sink.send(request("theirs", rename("theirs"))).await?;
+    let error = receive_message_of_type(&mut other_stream, "error").await?;
+    assert_eq!(error["data"]["id"], "theirs");
+    assert_eq!(error["data"]["reason"], "task_claimed");
+    assert_eq!(error["data"]["holder"], serde_json::to_value(&user)?);
+    ws_sink.send(request("mine", rename("mine"))).await?;
+    let ack = receive_message_of_type(&mut ws_stream, "ack").await?;
+    assert_eq!(ack["data"]["id"], "mine");
+
+    // Closing the holder's connection releases the lease right away
+    ws_sink.close().await?;
+    loop {
+        let presence = receive_message_of_type(&mut other_stream, "presence").await?;
+        if presence["data"]
+            .as_array()
+            .is_some_and(|presence| presence.iter().all(|p| p.get("lease").is_none()))
+        {
+            break;
+        }
+    }
+    other_sink.send(request("theirs", rename("theirs"))).await?;
+    let ack = receive_message_of_type(&mut other_stream, "ack").await?;
+    assert_eq!(ack["data"]["id"], "theirs");
+
+    // Leases expire unless renewed
+    other_sink
+        .send(request(
+            "claim",
+            serde_json::json!({ "type": "claim_task", "data": task }),
+        ))
+        .await?;
+    receive_message_of_type(&mut other_stream, "ack").await?;
+    let mut claimed = false;
+    loop {
+        let presence = receive_message_of_type(&mut other_stream, "presence").await?;
+        let lease = presence["data"]
+            .as_array()
+            .context("Presence is a list")?
+            .iter()
+            .find(|presence| presence["user"] == serde_json::to_value(&other_user).unwrap())
+            .context("Other user is present")?
+            .get("lease")
+            .cloned();
+        match lease {
+            Some(lease) => {
+                assert_eq!(lease["task"], task);
+                claimed = true;
+            }
+            None if claimed => break,
+            None => (),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn joined_lists_count_unseen_changes() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+    let _user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+
+    let other_jar = Arc::new(Jar::default());
+    let mut other_client = Client::builder()
+        .cookie_provider(other_jar.clone())
+        .build()?;
+    let _other_user = app.get_user(&mut other_client).await?;
+    let (mut other_sink, mut other_stream) =
+        app.connect_to_todo_list(todo_list_id, &other_jar).await?;
+    receive_todo_list(&mut other_stream).await?;
+    let joined = app.get_joined_todo_lists(&other_client).await?;
+    assert_eq!(joined[0].unread_changes(), 0);
+    let visited_at = serde_json::to_value(&joined[0])?["last_visited_at"].clone();
+    assert!(!visited_at.is_null());
+
+    // Leaving counts as a visit too
+    other_sink.close().await?;
+    timeout(Duration::from_secs(1), async {
+        loop {
+            let joined = app.get_joined_todo_lists(&other_client).await?;
+            if serde_json::to_value(&joined[0])?["last_visited_at"] != visited_at {
+                return anyhow::Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .context("Leaving wasn't recorded")??;
+
+    client
+        .post(format!("{}/todos/{}/commands", app.address, todo_list_id))
+        .json(&serde_json::json!([{ "type": "create_task" }, { "type": "create_task" }]))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let joined = app.get_joined_todo_lists(&other_client).await?;
+    assert_eq!(joined[0].unread_changes(), 2);
+    assert!(joined[0].changed_since_last_visit());
+    // Changes made by the user themselves are seen
+    let joined = app.get_joined_todo_lists(&client).await?;
+    assert_eq!(joined[0].unread_changes(), 0);
+    assert!(!joined[0].changed_since_last_visit());
+
+    let response = other_client
+        .post(format!("{}/todos/{}/seen", app.address, todo_list_id))
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 204);
+    let joined = app.get_joined_todo_lists(&other_client).await?;
+    assert_eq!(joined[0].unread_changes(), 0);
+    assert!(!joined[0].changed_since_last_visit());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lists_left_by_everyone_are_stored_before_being_unloaded() -> anyhow::Result<()> {
+    let app = TestApp::spawn().await;
+    let mut client = Client::builder().cookie_store(true).build()?;
+    let user = app.get_user(&mut client).await?;
+    let todo_list_id = app.create_todo_list(&mut client).await?;
+    let settings = coodo_be::settings::get_settings()?;
+    let state = AppState::new(app.redis_pool(), &settings.todo_handler);
+
+    let connection = state.join_todo_list(todo_list_id, *user.id()).await?;
+    let (command, reply) = Command::CreateTask.with_issuer(user.clone()).with_reply();
+    connection.command_tx.send(command).await?;
+    reply.await??;
+
+    // The connection is still around, so the list's task can't have stored the list on exit
+    state
+        .leave_todo_list(todo_list_id, *user.id(), connection.id)
+        .await;
+    let stored = TodoList::from_redis(todo_list_id, app.redis_pool()).await?;
+    assert_eq!(stored.tasks().len(), 1);
+
+    Ok(())
+}